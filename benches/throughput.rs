@@ -0,0 +1,244 @@
+//! Get/set throughput and latency, across thread counts, for uniform and zipf
+//! key distributions. With the `compare` feature, same workloads are also run
+//! on `moka` and on `lru` behind a mutex.
+//!
+//! Throughput is reported by criterion, latency percentiles are printed to
+//! stderr at the end of each group.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use std::sync::Arc;
+#[cfg(feature = "compare")]
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clru::{Lru, LruBuilder};
+
+const N_KEYS: usize = 100_000;
+const MAX_ENTRIES: usize = 10_000;
+const THREADS: [usize; 4] = [1, 2, 4, 8];
+const SEED: u64 = 0x5eed;
+
+#[derive(Clone, Copy, Debug)]
+enum Workload {
+    Uniform,
+    Zipf,
+}
+
+impl Workload {
+    fn keys(&self, seed: u64, n: usize) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Workload::Uniform => {
+                (0..n).map(|_| rng.gen_range(0..N_KEYS as u64)).collect()
+            }
+            Workload::Zipf => {
+                let zipf = Zipf::new(N_KEYS, 1.0);
+                (0..n).map(|_| zipf.sample(&mut rng)).collect()
+            }
+        }
+    }
+}
+
+// zipf distribution over `0..n`, sampled by binary search on its cdf.
+struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize, s: f64) -> Zipf {
+        let mut cdf: Vec<f64> = Vec::with_capacity(n);
+        let mut sum = 0.0;
+        for k in 1..=n {
+            sum += 1.0 / (k as f64).powf(s);
+            cdf.push(sum);
+        }
+        cdf.iter_mut().for_each(|p| *p /= sum);
+        Zipf { cdf }
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> u64 {
+        let p: f64 = rng.gen();
+        match self.cdf.binary_search_by(|x| x.total_cmp(&p)) {
+            Ok(off) | Err(off) => std::cmp::min(off, self.cdf.len() - 1) as u64,
+        }
+    }
+}
+
+// cache under benchmark, a handle is cloned for each thread.
+trait Cache: Clone + Send + 'static {
+    const NAME: &'static str;
+
+    fn get(&self, key: u64) -> Option<u64>;
+
+    fn set(&mut self, key: u64, value: u64);
+}
+
+#[derive(Clone)]
+struct Clru(Lru<u64, u64>);
+
+impl Cache for Clru {
+    const NAME: &'static str = "clru";
+
+    fn get(&self, key: u64) -> Option<u64> {
+        self.0.get(&key).unwrap()
+    }
+
+    fn set(&mut self, key: u64, value: u64) {
+        self.0.set(key, value).unwrap();
+    }
+}
+
+#[cfg(feature = "compare")]
+#[derive(Clone)]
+struct Moka(moka::sync::Cache<u64, u64>);
+
+#[cfg(feature = "compare")]
+impl Cache for Moka {
+    const NAME: &'static str = "moka";
+
+    fn get(&self, key: u64) -> Option<u64> {
+        self.0.get(&key)
+    }
+
+    fn set(&mut self, key: u64, value: u64) {
+        self.0.insert(key, value)
+    }
+}
+
+#[cfg(feature = "compare")]
+#[derive(Clone)]
+struct MutexLru(Arc<Mutex<lru::LruCache<u64, u64>>>);
+
+#[cfg(feature = "compare")]
+impl Cache for MutexLru {
+    const NAME: &'static str = "lru";
+
+    fn get(&self, key: u64) -> Option<u64> {
+        self.0.lock().unwrap().get(&key).copied()
+    }
+
+    fn set(&mut self, key: u64, value: u64) {
+        self.0.lock().unwrap().put(key, value);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Get,
+    Set,
+}
+
+// run `iters` operations split across `n_threads`, return the elapsed time
+// and, per operation, latencies.
+fn run<C: Cache>(
+    cache: &C,
+    op: Op,
+    keys: &Arc<Vec<u64>>,
+    n_threads: usize,
+    iters: u64,
+) -> (Duration, Vec<Duration>) {
+    let per_thread = std::cmp::max(iters as usize / n_threads, 1);
+    let start = Instant::now();
+    let handles: Vec<thread::JoinHandle<Vec<Duration>>> = (0..n_threads)
+        .map(|id| {
+            let (mut cache, keys) = (cache.clone(), Arc::clone(keys));
+            thread::spawn(move || {
+                let mut latencies = Vec::with_capacity(per_thread);
+                let offset = id * (keys.len() / n_threads);
+                for i in 0..per_thread {
+                    let key = keys[(offset + i) % keys.len()];
+                    let now = Instant::now();
+                    match op {
+                        Op::Get => {
+                            criterion::black_box(cache.get(key));
+                        }
+                        Op::Set => cache.set(key, key),
+                    }
+                    latencies.push(now.elapsed());
+                }
+                latencies
+            })
+        })
+        .collect();
+
+    let mut latencies = vec![];
+    for handle in handles.into_iter() {
+        latencies.extend(handle.join().unwrap())
+    }
+    (start.elapsed(), latencies)
+}
+
+fn percentile(latencies: &mut [Duration], p: f64) -> Duration {
+    latencies.sort_unstable();
+    let off = ((latencies.len() as f64) * p) as usize;
+    latencies
+        .get(std::cmp::min(off, latencies.len().saturating_sub(1)))
+        .copied()
+        .unwrap_or_default()
+}
+
+fn bench_cache<C: Cache>(c: &mut Criterion, cache: C) {
+    for workload in [Workload::Uniform, Workload::Zipf].iter() {
+        let keys = Arc::new(workload.keys(SEED, N_KEYS));
+
+        // warm the cache, so that gets are a mix of hits and misses.
+        let mut warm = cache.clone();
+        keys.iter().for_each(|key| warm.set(*key, *key));
+
+        for op in [Op::Get, Op::Set].iter() {
+            let name = format!("{}/{:?}/{:?}", C::NAME, workload, op);
+            let mut group = c.benchmark_group(&name);
+            for n_threads in THREADS.iter() {
+                let mut latencies = vec![];
+                group.throughput(Throughput::Elements(1));
+                group.bench_with_input(
+                    BenchmarkId::from_parameter(n_threads),
+                    n_threads,
+                    |b, &n_threads| {
+                        b.iter_custom(|iters| {
+                            let (elapsed, lats) =
+                                run(&cache, *op, &keys, n_threads, iters);
+                            latencies = lats;
+                            elapsed
+                        })
+                    },
+                );
+                eprintln!(
+                    "{}/{} p50:{:?} p99:{:?}",
+                    name,
+                    n_threads,
+                    percentile(&mut latencies, 0.50),
+                    percentile(&mut latencies, 0.99),
+                );
+            }
+            group.finish();
+        }
+    }
+}
+
+fn bench_clru(c: &mut Criterion) {
+    let config = LruBuilder { max_entries: MAX_ENTRIES, ..LruBuilder::default() };
+    bench_cache(c, Clru(config.build_default()));
+}
+
+#[cfg(feature = "compare")]
+fn bench_moka(c: &mut Criterion) {
+    let cache = moka::sync::Cache::new(MAX_ENTRIES as u64);
+    bench_cache(c, Moka(cache));
+}
+
+#[cfg(feature = "compare")]
+fn bench_lru(c: &mut Criterion) {
+    let size = std::num::NonZeroUsize::new(MAX_ENTRIES).unwrap();
+    let cache = lru::LruCache::new(size);
+    bench_cache(c, MutexLru(Arc::new(Mutex::new(cache))));
+}
+
+#[cfg(not(feature = "compare"))]
+criterion_group!(benches, bench_clru);
+#[cfg(feature = "compare")]
+criterion_group!(benches, bench_clru, bench_moka, bench_lru);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+//! Derive macros for [clru](https://github.com/prataprc/clru).
+//!
+//! `#[derive(HeapSize)]` implements `clru::HeapSize` for structs and enums, by
+//! summing up the heap size of every field. All field types must implement
+//! `HeapSize`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+#[proc_macro_derive(HeapSize)]
+pub fn derive_heap_size(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::clru::HeapSize));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let body = heap_size_body(&input.data);
+
+    let expanded = quote! {
+        impl #impl_generics ::clru::HeapSize for #name #ty_generics #where_clause {
+            fn heap_size(&self) -> usize {
+                #body
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+fn heap_size_body(data: &Data) -> TokenStream2 {
+    match data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let names = fields.named.iter().map(|f| &f.ident);
+                quote! { 0 #(+ ::clru::HeapSize::heap_size(&self.#names))* }
+            }
+            Fields::Unnamed(fields) => {
+                let indexes = (0..fields.unnamed.len()).map(Index::from);
+                quote! { 0 #(+ ::clru::HeapSize::heap_size(&self.#indexes))* }
+            }
+            Fields::Unit => quote! { 0 },
+        },
+        Data::Enum(data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let vname = &variant.ident;
+                match &variant.fields {
+                    Fields::Named(fields) => {
+                        let names: Vec<_> =
+                            fields.named.iter().map(|f| &f.ident).collect();
+                        quote! {
+                            Self::#vname { #(#names),* } => {
+                                0 #(+ ::clru::HeapSize::heap_size(#names))*
+                            }
+                        }
+                    }
+                    Fields::Unnamed(fields) => {
+                        let names: Vec<_> = (0..fields.unnamed.len())
+                            .map(|i| format_ident!("f{}", i))
+                            .collect();
+                        quote! {
+                            Self::#vname(#(#names),*) => {
+                                0 #(+ ::clru::HeapSize::heap_size(#names))*
+                            }
+                        }
+                    }
+                    Fields::Unit => quote! { Self::#vname => 0, },
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            syn::Error::new(proc_macro2::Span::call_site(), "HeapSize on union")
+                .to_compile_error()
+        }
+    }
+}
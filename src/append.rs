@@ -0,0 +1,17 @@
+/// Trait for values that can be extended in place, refer [crate::Lru::append].
+pub trait Append<T: ?Sized> {
+    /// Extend this value with `tail`.
+    fn append(&mut self, tail: &T);
+}
+
+impl Append<[u8]> for Vec<u8> {
+    fn append(&mut self, tail: &[u8]) {
+        self.extend_from_slice(tail)
+    }
+}
+
+impl Append<str> for String {
+    fn append(&mut self, tail: &str) {
+        self.push_str(tail)
+    }
+}
@@ -0,0 +1,554 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+use std::sync::{Arc, RwLock};
+
+/// Backing map used when none is specified, cmap when the `cmap` feature is
+/// enabled, which is the default, else [Sharded].
+#[cfg(feature = "cmap")]
+pub type DefaultMap<K, V, H> = cmap::Map<K, V, H>;
+#[cfg(not(feature = "cmap"))]
+pub type DefaultMap<K, V, H> = Sharded<K, V, H>;
+
+/// Hasher used when none is specified, refer [crate::LruBuilder::build_default].
+///
+/// With the `ahash` feature, which is the default, this is aHash, keyed with
+/// random state per instance, which is fast and resists hash-flooding as long
+/// as the keys are not observable to an attacker. Otherwise this is std's
+/// SipHash based `RandomState`, slower but a cryptographically stronger
+/// defense against hash-flooding on untrusted keys.
+#[cfg(feature = "ahash")]
+pub type DefaultHasher = ahash::RandomState;
+#[cfg(not(feature = "ahash"))]
+pub type DefaultHasher = std::collections::hash_map::RandomState;
+
+/// Hasher with a fixed seed, refer [crate::LruBuilder::deterministic].
+///
+/// Same key hashes to the same value on every run, for a given seed and
+/// build of this crate, so that tests and simulations can be replayed. It
+/// offers no defense against hash-flooding, don't use it on untrusted keys.
+#[derive(Clone, Debug, Default)]
+pub struct SeededState {
+    seed: u64,
+}
+
+impl SeededState {
+    pub fn new(seed: u64) -> SeededState {
+        SeededState { seed }
+    }
+}
+
+impl BuildHasher for SeededState {
+    type Hasher = std::collections::hash_map::DefaultHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        // std's DefaultHasher::new() is SipHash keyed with zeros.
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hasher.write_u64(self.seed);
+        hasher
+    }
+}
+
+/// Backing map for cached entries, refer [crate::LruBuilder::build_backend].
+///
+/// Map is shared by all cache handles and the evictor thread, `cloned` shall
+/// return a handle to the same underlying map, not a copy of it. Callbacks
+/// passed to `get_with` may hold a read-lock on the entry, they shall not
+/// call back into the map.
+pub trait Backend<K, V, H>: Send + Sized {
+    /// Create a new map, `concurrency` is the expected number of threads
+    /// accessing the map.
+    fn new(concurrency: usize, hash_builder: H) -> Self;
+
+    /// Return a new handle to the same map.
+    fn cloned(&self) -> Self;
+
+    /// Lookup `key` and apply `callb` on its value.
+    fn get_with<Q, F, T>(&self, key: &Q, callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> T;
+
+    /// Insert `value` for `key`, return the replaced value, if any.
+    fn set(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Remove `key`, return its value, if any.
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized;
+
+    /// Remove `key` only if `pred` holds over its value, return the removed
+    /// value, if any. Default implementation checks and removes in two steps,
+    /// a concurrent set in between can be removed. Backends that can do both
+    /// under the same lock shall override it.
+    fn remove_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> bool,
+    {
+        match self.get_with(key, pred) {
+            Some(true) => self.remove(key),
+            _ => None,
+        }
+    }
+
+    /// Set how keys' hashes are folded into the map's shards, refer [Fold].
+    /// Default implementation ignores it, for maps that fold hashes their own
+    /// way, like cmap.
+    fn set_fold(&mut self, _fold: Fold) {}
+
+    /// Apply `callb` on the value of `key`, to update it in place, and return
+    /// its result, None if `key` is missing. Default implementation is for
+    /// backends that can't hand out their values mutably, it returns None
+    /// without calling `callb`, callers shall then fall back to a set.
+    fn update_with<Q, F, T>(&mut self, _key: &Q, _callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnOnce(&mut V) -> T,
+    {
+        None
+    }
+}
+
+#[cfg(feature = "cmap")]
+impl<K, V, H> Backend<K, V, H> for cmap::Map<K, V, H>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher,
+{
+    fn new(concurrency: usize, hash_builder: H) -> Self {
+        cmap::Map::new(concurrency, hash_builder)
+    }
+
+    fn cloned(&self) -> Self {
+        cmap::Map::cloned(self)
+    }
+
+    fn get_with<Q, F, T>(&self, key: &Q, callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> T,
+    {
+        cmap::Map::get_with(self, key, callb)
+    }
+
+    fn set(&mut self, key: K, value: V) -> Option<V> {
+        cmap::Map::set(self, key, value)
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        cmap::Map::remove(self, key)
+    }
+}
+
+/// Backend using [dashmap::DashMap], enabled by the `dashmap` feature.
+#[cfg(feature = "dashmap")]
+pub struct DashMap<K, V, H>(Arc<dashmap::DashMap<K, V, H>>);
+
+#[cfg(feature = "dashmap")]
+impl<K, V, H> Backend<K, V, H> for DashMap<K, V, H>
+where
+    K: Send + Sync + Eq + Hash,
+    V: Send + Sync,
+    H: Send + Sync + Clone + BuildHasher,
+{
+    fn new(concurrency: usize, hash_builder: H) -> Self {
+        // dashmap wants a power-of-two shard count, greater than one.
+        let shards = std::cmp::max(concurrency * 4, 2).next_power_of_two();
+        let map = dashmap::DashMap::with_hasher_and_shard_amount(hash_builder, shards);
+        DashMap(Arc::new(map))
+    }
+
+    fn cloned(&self) -> Self {
+        DashMap(Arc::clone(&self.0))
+    }
+
+    fn get_with<Q, F, T>(&self, key: &Q, mut callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> T,
+    {
+        self.0.get(key).map(|entry| callb(entry.value()))
+    }
+
+    fn set(&mut self, key: K, value: V) -> Option<V> {
+        self.0.insert(key, value)
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.0.remove(key).map(|(_, value)| value)
+    }
+
+    fn remove_if<Q, F>(&mut self, key: &Q, mut pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> bool,
+    {
+        self.0.remove_if(key, |_, value| pred(value)).map(|(_, value)| value)
+    }
+
+    fn update_with<Q, F, T>(&mut self, key: &Q, callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnOnce(&mut V) -> T,
+    {
+        self.0.get_mut(key).map(|mut entry| callb(entry.value_mut()))
+    }
+}
+
+/// Backend using a fixed set of `RwLock<HashMap>` shards, depends only on std.
+///
+/// Key's shard is picked from its hash, lookups take a read-lock and updates
+/// take a write-lock on that shard alone.
+pub struct Sharded<K, V, H> {
+    shards: Arc<Vec<RwLock<HashMap<K, V, H>>>>,
+    hash_builder: H,
+    fold: Fold,
+}
+
+impl<K, V, H> Sharded<K, V, H>
+where
+    H: BuildHasher,
+{
+    fn to_shard<Q>(&self, key: &Q) -> &RwLock<HashMap<K, V, H>>
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        &self.shards[to_shard_off(hash, self.shards.len(), self.fold)]
+    }
+}
+
+/// Folding of a key's 64-bit hash into a shard, by [Sharded] and [Tiered]
+/// backends, refer [crate::LruBuilder::shard_fold].
+///
+/// Shard's HashMap buckets keys using the same hash, picking the shard from
+/// the hash's raw bits would leave keys within a shard agreeing on those
+/// bits, collapsing them into fewer buckets, especially for sequential keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fold {
+    /// Pick the shard from the high bits of a second, murmur3 fmix64, round.
+    /// This is the default.
+    #[default]
+    Mix,
+    /// Pick the shard from the high and low halves of the hash, xor-ed.
+    Xor,
+    /// Pick the shard from the high bits of the hash as is, saves the second
+    /// round for hashers that already mix their output well.
+    High,
+}
+
+fn to_shard_off(hash: u64, n_shards: usize, fold: Fold) -> usize {
+    let h = match fold {
+        Fold::Mix => {
+            let mut h = hash;
+            h ^= h >> 33;
+            h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+            h ^= h >> 33;
+            h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+            h ^= h >> 33;
+            h >> 32
+        }
+        Fold::Xor => (hash ^ (hash >> 32)) & 0xffff_ffff,
+        Fold::High => hash >> 32,
+    };
+    ((h * (n_shards as u64)) >> 32) as usize
+}
+
+impl<K, V, H> Backend<K, V, H> for Sharded<K, V, H>
+where
+    K: Send + Sync + Eq + Hash,
+    V: Send + Sync,
+    H: Send + Sync + Clone + BuildHasher,
+{
+    fn new(concurrency: usize, hash_builder: H) -> Self {
+        let n = std::cmp::max(concurrency * 4, 1);
+        let shards = (0..n)
+            .map(|_| RwLock::new(HashMap::with_hasher(hash_builder.clone())))
+            .collect();
+        Sharded {
+            shards: Arc::new(shards),
+            hash_builder,
+            fold: Fold::default(),
+        }
+    }
+
+    fn cloned(&self) -> Self {
+        Sharded {
+            shards: Arc::clone(&self.shards),
+            hash_builder: self.hash_builder.clone(),
+            fold: self.fold,
+        }
+    }
+
+    fn set_fold(&mut self, fold: Fold) {
+        self.fold = fold;
+    }
+
+    fn get_with<Q, F, T>(&self, key: &Q, callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> T,
+    {
+        let shard = self.to_shard(key).read().unwrap();
+        shard.get(key).map(callb)
+    }
+
+    fn set(&mut self, key: K, value: V) -> Option<V> {
+        self.to_shard(&key).write().unwrap().insert(key, value)
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.to_shard(key).write().unwrap().remove(key)
+    }
+
+    fn remove_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> bool,
+    {
+        let mut shard = self.to_shard(key).write().unwrap();
+        match shard.get(key).map(pred) {
+            Some(true) => shard.remove(key),
+            _ => None,
+        }
+    }
+
+    fn update_with<Q, F, T>(&mut self, key: &Q, callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnOnce(&mut V) -> T,
+    {
+        self.to_shard(key).write().unwrap().get_mut(key).map(callb)
+    }
+}
+
+/// Number of entries held in hot maps, across all shards, refer [Tiered].
+/// Sized for the hot maps to stay within L2/L3 cache.
+pub const HOT_ENTRIES: usize = 4096;
+
+// number of hits, in the cold map, after which an entry is promoted.
+const PROMOTE_HITS: u32 = 4;
+
+type Tier<K, V, H> = RwLock<HashMap<K, (V, AtomicU32), H>>;
+type Tiers<K, V, H> = (Tier<K, V, H>, Tier<K, V, H>);
+
+/// Backend tiering entries into hot maps, holding up to [HOT_ENTRIES] of the
+/// most frequently read entries, and cold maps holding the rest.
+///
+/// Like [Sharded], key's shard is picked from its hash, each shard has a small
+/// hot map and a cold map. Entries are set into the cold map and promoted to
+/// the hot map after a few hits. When the hot map is full, its least read
+/// entry is demoted to the cold map and hit counts in the hot map are halved,
+/// so that entries that cool down make way for others. Lookups check the hot
+/// map first.
+pub struct Tiered<K, V, H> {
+    // (hot, cold) maps for each shard.
+    shards: Arc<Vec<Tiers<K, V, H>>>,
+    // capacity of each hot map.
+    hot_cap: usize,
+    hash_builder: H,
+    fold: Fold,
+}
+
+impl<K, V, H> Tiered<K, V, H>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher,
+{
+    fn to_shard<Q>(&self, key: &Q) -> &Tiers<K, V, H>
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        &self.shards[to_shard_off(hash, self.shards.len(), self.fold)]
+    }
+
+    // move entry from cold to hot, demoting the least read entry if the hot
+    // map is full. Locks are always taken cold first and then hot.
+    fn promote<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (hot, cold) = self.to_shard(key);
+        let mut cold = cold.write().unwrap();
+        let (key, (value, _)) = match cold.remove_entry(key) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let mut hot = hot.write().unwrap();
+        if hot.len() >= self.hot_cap {
+            let victim = hot
+                .iter()
+                .min_by_key(|(_, (_, hits))| hits.load(SeqCst))
+                .map(|(key, _)| key.clone());
+            if let Some((vkey, (vvalue, _))) =
+                victim.and_then(|k| hot.remove_entry::<K>(&k))
+            {
+                cold.insert(vkey, (vvalue, AtomicU32::new(0)));
+            }
+            hot.values().for_each(|(_, hits)| {
+                hits.store(hits.load(SeqCst) / 2, SeqCst);
+            });
+        }
+        hot.insert(key, (value, AtomicU32::new(0)));
+    }
+}
+
+impl<K, V, H> Backend<K, V, H> for Tiered<K, V, H>
+where
+    K: Send + Sync + Clone + Eq + Hash,
+    V: Send + Sync,
+    H: Send + Sync + Clone + BuildHasher,
+{
+    fn new(concurrency: usize, hash_builder: H) -> Self {
+        let n = std::cmp::max(concurrency * 4, 1);
+        let shards = (0..n)
+            .map(|_| {
+                let hot = RwLock::new(HashMap::with_hasher(hash_builder.clone()));
+                let cold = RwLock::new(HashMap::with_hasher(hash_builder.clone()));
+                (hot, cold)
+            })
+            .collect();
+        let hot_cap = std::cmp::max(HOT_ENTRIES / n, 1);
+        let fold = Fold::default();
+        Tiered {
+            shards: Arc::new(shards),
+            hot_cap,
+            hash_builder,
+            fold,
+        }
+    }
+
+    fn cloned(&self) -> Self {
+        Tiered {
+            shards: Arc::clone(&self.shards),
+            hot_cap: self.hot_cap,
+            hash_builder: self.hash_builder.clone(),
+            fold: self.fold,
+        }
+    }
+
+    fn set_fold(&mut self, fold: Fold) {
+        self.fold = fold;
+    }
+
+    fn get_with<Q, F, T>(&self, key: &Q, mut callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> T,
+    {
+        let (hot, cold) = self.to_shard(key);
+        if let Some(res) = hot.read().unwrap().get(key).map(|(value, hits)| {
+            hits.fetch_add(1, SeqCst);
+            callb(value)
+        }) {
+            return Some(res);
+        }
+
+        let (res, promote) = {
+            let cold = cold.read().unwrap();
+            match cold.get(key) {
+                Some((value, hits)) => {
+                    let n = hits.fetch_add(1, SeqCst) + 1;
+                    (Some(callb(value)), n >= PROMOTE_HITS)
+                }
+                // entry might have been promoted after the hot lookup, which
+                // can't happen while holding the cold lock.
+                None => (hot.read().unwrap().get(key).map(|(v, _)| callb(v)), false),
+            }
+        };
+        if promote {
+            self.promote(key);
+        }
+        res
+    }
+
+    fn set(&mut self, key: K, value: V) -> Option<V> {
+        let (hot, cold) = self.to_shard(&key);
+        let mut cold = cold.write().unwrap();
+        let mut hot = hot.write().unwrap();
+        match hot.get_mut(&key) {
+            Some((old, _)) => Some(std::mem::replace(old, value)),
+            None => {
+                let value = (value, AtomicU32::new(0));
+                cold.insert(key, value).map(|(old, _)| old)
+            }
+        }
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (hot, cold) = self.to_shard(key);
+        let mut cold = cold.write().unwrap();
+        let mut hot = hot.write().unwrap();
+        match hot.remove(key) {
+            Some((value, _)) => Some(value),
+            None => cold.remove(key).map(|(value, _)| value),
+        }
+    }
+
+    fn remove_if<Q, F>(&mut self, key: &Q, mut pred: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnMut(&V) -> bool,
+    {
+        let (hot, cold) = self.to_shard(key);
+        let mut cold = cold.write().unwrap();
+        let mut hot = hot.write().unwrap();
+        if let Some(ok) = hot.get(key).map(|(value, _)| pred(value)) {
+            return if ok { hot.remove(key).map(|(value, _)| value) } else { None };
+        }
+        match cold.get(key).map(|(value, _)| pred(value)) {
+            Some(true) => cold.remove(key).map(|(value, _)| value),
+            _ => None,
+        }
+    }
+
+    fn update_with<Q, F, T>(&mut self, key: &Q, callb: F) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnOnce(&mut V) -> T,
+    {
+        let (hot, cold) = self.to_shard(key);
+        let mut cold = cold.write().unwrap();
+        let mut hot = hot.write().unwrap();
+        match hot.get_mut(key) {
+            Some((value, _)) => Some(callb(value)),
+            None => cold.get_mut(key).map(|(value, _)| callb(value)),
+        }
+    }
+}
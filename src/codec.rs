@@ -0,0 +1,125 @@
+//! Module implement codecs for cache snapshots, refer `Lru::save`.
+
+#[cfg(feature = "encrypt")]
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+#[cfg(feature = "encrypt")]
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{de::DeserializeOwned, Serialize};
+
+#[cfg(any(
+    feature = "bincode",
+    feature = "cbor",
+    feature = "msgpack",
+    feature = "encrypt"
+))]
+use crate::Error;
+
+#[cfg(feature = "encrypt")]
+const NONCE_SIZE: usize = 24; // XChaCha20 nonce.
+use crate::Result;
+
+/// Trait to encode and decode snapshot records, refer [crate::Lru::save].
+/// Implement this to persist snapshots in other formats.
+pub trait Codec {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>>;
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T>;
+}
+
+/// Compact binary encoding via `bincode`, meant for snapshots read back by
+/// this crate. Enabled by the `bincode` feature.
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        err_at!(FailConvert, bincode::serialize(value))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        err_at!(FailConvert, bincode::deserialize(data))
+    }
+}
+
+/// CBOR, RFC 8949, via `serde_cbor`, readable from most languages. Records
+/// are encoded as maps keyed by field name. Enabled by the `cbor` feature.
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        err_at!(FailConvert, serde_cbor::to_vec(value))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        err_at!(FailConvert, serde_cbor::from_slice(data))
+    }
+}
+
+/// MessagePack via `rmp-serde`, readable from most languages. Records are
+/// encoded as maps keyed by field name. Enabled by the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPack {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        err_at!(FailConvert, rmp_serde::to_vec_named(value))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        err_at!(FailConvert, rmp_serde::from_slice(data))
+    }
+}
+
+/// Wrap `codec` with authenticated encryption, XChaCha20-Poly1305, using a
+/// caller supplied 256-bit key. Each record is sealed with a random nonce,
+/// stored ahead of it. Records that fail to authenticate, say when loaded
+/// with another key, or tampered with, fail to decode, refer
+/// [crate::LoadReport]. Enabled by the `encrypt` feature.
+#[cfg(feature = "encrypt")]
+#[derive(Clone)]
+pub struct Encrypted<C> {
+    codec: C,
+    cipher: XChaCha20Poly1305,
+}
+
+#[cfg(feature = "encrypt")]
+impl<C> Encrypted<C> {
+    pub fn new(codec: C, key: &[u8; 32]) -> Encrypted<C> {
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+        Encrypted { codec, cipher }
+    }
+}
+
+#[cfg(feature = "encrypt")]
+impl<C> Codec for Encrypted<C>
+where
+    C: Codec,
+{
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let data = self.codec.encode(value)?;
+        let sealed = err_at!(FailConvert, self.cipher.encrypt(&nonce, data.as_ref()))?;
+
+        let mut out = Vec::with_capacity(nonce.len() + sealed.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        if data.len() < NONCE_SIZE {
+            return err_at!(FailConvert, msg: "sealed record of {} bytes", data.len());
+        }
+        let (nonce, sealed) = data.split_at(NONCE_SIZE);
+        let nonce = XNonce::from_slice(nonce);
+        let data = err_at!(FailConvert, self.cipher.decrypt(nonce, sealed))?;
+        self.codec.decode(&data)
+    }
+}
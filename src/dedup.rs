@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+// table is not purged until it holds as many hashes.
+const PURGE_MIN: usize = 64;
+
+type Refs<V> = HashMap<u64, Vec<Weak<V>>>;
+
+/// Table of values shared by several keys, indexed by value's hash.
+///
+/// Table only holds weak references, values are reference counted by the
+/// entries referring to them and freed when the last such entry is dropped.
+/// Stale references are purged while interning values with same hash, and
+/// the whole table is purged once it doubles in size since the last purge,
+/// so that its size stays proportional to the live values.
+pub struct Table<V> {
+    // (values, number of hashes that triggers the next purge)
+    values: Mutex<(Refs<V>, usize)>,
+}
+
+impl<V> Default for Table<V> {
+    fn default() -> Table<V> {
+        Table { values: Mutex::new((HashMap::new(), PURGE_MIN)) }
+    }
+}
+
+impl<V> Table<V> {
+    /// Return the shared copy of `value`, adding it to the table if missing.
+    pub fn intern(&self, hash: u64, value: V) -> Arc<V>
+    where
+        V: PartialEq,
+    {
+        let mut guard = self.values.lock().unwrap();
+        let (values, purge_at) = &mut *guard;
+
+        if values.len() >= *purge_at {
+            values.retain(|_, refs| {
+                refs.retain(|r| r.strong_count() > 0);
+                !refs.is_empty()
+            });
+            *purge_at = std::cmp::max(values.len() * 2, PURGE_MIN);
+        }
+
+        let refs = values.entry(hash).or_insert_with(Vec::default);
+        refs.retain(|r| r.strong_count() > 0);
+
+        match refs.iter().filter_map(|r| r.upgrade()).find(|v| v.as_ref() == &value) {
+            Some(shared) => shared,
+            None => {
+                let shared = Arc::new(value);
+                refs.push(Arc::downgrade(&shared));
+                shared
+            }
+        }
+    }
+
+    /// Return the number of distinct hashes held in the table.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().0.len()
+    }
+}
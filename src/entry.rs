@@ -0,0 +1,66 @@
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{Backend, Lru, Result, Value};
+
+/// Entry returned by [Lru::get_entry], a consistent snapshot of the entry's
+/// value and metadata, taken in a single lookup.
+pub struct EntryRef<'a, K, V, H, M> {
+    pub(crate) lru: &'a Lru<K, V, H, M>,
+    pub(crate) key: K,
+    pub(crate) value: V,
+    pub(crate) born: Duration, // elapsed time since UNIX_EPOCH.
+    pub(crate) expires: Option<Duration>, // elapsed time since UNIX_EPOCH.
+    pub(crate) stale: Option<Duration>, // elapsed time since UNIX_EPOCH.
+}
+
+impl<'a, K, V, H, M> EntryRef<'a, K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+{
+    /// Return the key, as stored in the cache.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Return the value.
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// Consume the entry and return its value.
+    pub fn into_value(self) -> V {
+        self.value
+    }
+
+    /// Return the time elapsed since the entry was inserted.
+    pub fn age(&self) -> Duration {
+        let now = UNIX_EPOCH.elapsed().unwrap_or_default();
+        now.checked_sub(self.born).unwrap_or_default()
+    }
+
+    /// Return the time left before this entry expires, due to `max_old`,
+    /// `time_to_idle` or `soft_ttl` plus `stale_grace`, as of lookup. Return
+    /// None if entry does not expire.
+    pub fn ttl_remaining(&self) -> Option<Duration> {
+        let now = UNIX_EPOCH.elapsed().unwrap_or_default();
+        self.expires.map(|expires| expires.checked_sub(now).unwrap_or_default())
+    }
+
+    /// Return whether the entry is older than `soft_ttl`, and shall be
+    /// refreshed before it expires.
+    pub fn is_stale(&self) -> bool {
+        let now = UNIX_EPOCH.elapsed().unwrap_or_default();
+        self.stale.is_some_and(|stale| now > stale)
+    }
+
+    /// Record an access on the entry, return false if entry is no more in the
+    /// cache.
+    pub fn touch(&self) -> Result<bool>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        Ok(self.lru.read(&self.key, |_| ())?.is_some())
+    }
+}
@@ -1,160 +1,902 @@
 use std::hash::{BuildHasher, Hash};
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
-use std::time::{Duration, UNIX_EPOCH};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use crate::{list, Error, Result, Value};
+use crate::handoff::{EvictionReason, Handoff};
+use crate::lru::{to_stats_with, PassStats, Reporter};
+use crate::quota::{Quota, Tally};
+use crate::telemetry::{Event, TelemetrySink};
+use crate::{expiry::Expiry, ghost::Ghost, invalidate::Invalidator, pins::Pins};
+use crate::{list, Backend, Error, Policy, Pressure, Result, Value};
 
 const MAX_SLEEP: f64 = 10.0; // in millisecons
+const MIN_SLEEP: f64 = 0.1; // in milliseconds, yield instead of shorter sleeps.
+const ADAPT_WINDOW: Duration = Duration::from_secs(1);
+const GROW_RATIO: usize = 10; // grow if 1 in 10 victims come back.
+const SHRINK_RATIO: usize = 100; // shrink if less than 1 in 100 victims come back.
+const ADAPT_STEP: usize = 10; // grow or shrink by 10% of max_entries.
+const PRESSURE_POLL: Duration = Duration::from_secs(1);
+
+/// Counters updated by cache handles and the evictor.
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) n_gets: AtomicUsize,
+    pub(crate) n_sets: AtomicUsize,
+    pub(crate) n_rejected: AtomicUsize,
+    pub(crate) n_oversized: AtomicUsize,
+    pub(crate) n_demoted: AtomicUsize,
+    pub(crate) swaps: list::Retries,
+    pub(crate) n_evicted: AtomicUsize,
+    pub(crate) n_deleted: AtomicUsize,
+    pub(crate) n_older: AtomicUsize,
+    pub(crate) n_ghost_hits: AtomicUsize,
+    pub(crate) n_grows: AtomicUsize,
+    pub(crate) n_shrinks: AtomicUsize,
+    pub(crate) n_pressure: AtomicUsize,
+    pub(crate) n_invalidated: AtomicUsize,
+    pub(crate) n_expire_callbacks: AtomicUsize,
+    pub(crate) n_stale: AtomicUsize,
+    pub(crate) n_costed: AtomicUsize,
+    pub(crate) n_prioritized: AtomicUsize,
+    pub(crate) n_quota: AtomicUsize,
+    pub(crate) n_leak_alerts: AtomicUsize,
+    pub(crate) n_handoff: AtomicUsize,
+    pub(crate) n_handoff_dropped: AtomicUsize,
+    pub(crate) alive: AtomicBool,
+    pub(crate) last_pass: AtomicU64, // in uS since UNIX_EPOCH, 0 if none.
+    pub(crate) paused_since: AtomicU64, // in uS since UNIX_EPOCH, 0 if not paused.
+    pub(crate) time_paused: AtomicUsize, // in uS, across completed pauses.
+    pub(crate) passes: PassCounters,
+    pub(crate) last_pass_stats: PassCounters, // overwritten after every pass.
+}
+
+/// Work done by evictor passes, refer [crate::PassStats].
+#[derive(Default)]
+pub(crate) struct PassCounters {
+    pub(crate) n_passes: AtomicUsize,
+    pub(crate) n_scanned: AtomicUsize,
+    pub(crate) n_reclaimed: AtomicUsize,
+    pub(crate) n_evicted: AtomicUsize,
+    pub(crate) elapsed: AtomicUsize, // in uS.
+}
+
+impl PassCounters {
+    fn add(&self, stats: &PassStats) {
+        self.n_passes.fetch_add(stats.n_passes, SeqCst);
+        self.n_scanned.fetch_add(stats.n_scanned, SeqCst);
+        self.n_reclaimed.fetch_add(stats.n_reclaimed, SeqCst);
+        self.n_evicted.fetch_add(stats.n_evicted, SeqCst);
+        self.elapsed.fetch_add(stats.elapsed.as_micros() as usize, SeqCst);
+    }
+
+    fn store(&self, stats: &PassStats) {
+        self.n_passes.store(stats.n_passes, SeqCst);
+        self.n_scanned.store(stats.n_scanned, SeqCst);
+        self.n_reclaimed.store(stats.n_reclaimed, SeqCst);
+        self.n_evicted.store(stats.n_evicted, SeqCst);
+        self.elapsed.store(stats.elapsed.as_micros() as usize, SeqCst);
+    }
+
+    pub(crate) fn to_stats(&self, read: fn(&AtomicUsize) -> usize) -> PassStats {
+        PassStats {
+            n_passes: read(&self.n_passes),
+            n_scanned: read(&self.n_scanned),
+            n_reclaimed: read(&self.n_reclaimed),
+            n_evicted: read(&self.n_evicted),
+            elapsed: Duration::from_micros(read(&self.elapsed) as u64),
+        }
+    }
+}
 
 /// Evictor will remove an access node if,
 /// * Node is marked as deleted.
 /// * Node is older than configured elapsed time, optional.
+/// * Entry is not accessed for configured `time_to_idle`, optional.
+/// * Entry is older than configured `soft_ttl` plus `stale_grace`, optional.
 /// * Number of nodes in the access list exceed the count-limit, `max_entries`.
 /// * Memory footprint of cache exceeds size-limit, `max_memory`.
+///
+/// Entries matching a predicate registered via `Lru::invalidate_entries_if`
+/// are removed as the evictor walks past them.
+///
+/// With `Policy::Lfu`, count-limit victims are picked from the oldest entries,
+/// twice as many as the victims, by lowest aged access frequency instead of
+/// by position in the access list. Either way, entries demoted by the
+/// admission callback are picked ahead of others.
+///
+/// With cost hints, refer `Lru::set_with_cost`, `Policy::Lru` picks count-limit
+/// victims from the oldest entries, twice as many as the victims, cheapest
+/// first, and `Policy::Lfu` picks the cheapest among the same frequency.
+///
+/// Entries set with a lower priority, refer `Lru::set_with_priority`, are
+/// picked as count-limit victims ahead of those with higher priority, under
+/// every policy.
+///
+/// With `Policy::Gdsf`, count-limit and size-limit victims are picked by
+/// lowest aged access frequency per byte of footprint.
+///
+/// With `Policy::ClockPro`, each pass sweeps over entries flipping them hot or
+/// cold, and count-limit victims are picked from the unreferenced cold ones,
+/// oldest first. Victims are remembered in a ghost list, a key set back while
+/// in it starts hot.
+///
+/// With `adaptive_cap`, count-limit victims are remembered in a ghost list and
+/// `max_entries` is grown, up to the cap, when victims keep coming back, and
+/// shrunk, down to the configured limit, when they rarely do.
+///
+/// With `watermarks`, count-limit victims are picked only once the number of
+/// entries crosses the high mark, and then down to the low mark.
+///
+/// With `pressure`, count-limit is lowered to `pressure_floor` for as long as
+/// the host is under memory pressure.
+///
+/// With the `leak-detect` feature, the number of live access nodes is checked
+/// against the number of entries and tombstones after every pass.
+///
+/// While the cache is frozen, refer `Lru::freeze`, only deleted nodes are
+/// removed.
+///
+/// While eviction is paused, refer `Lru::pause_eviction`, count-limit,
+/// size-limit and quotas are not enforced, entries still expire.
+///
+/// With `protect_young`, entries inserted within that duration are not picked
+/// as count-limit, size-limit or quota victims, they still expire.
+///
+/// With a handoff channel, refer `LruBuilder::evict_to`, removed entries are
+/// sent to the consumer along with the reason, instead of being dropped.
+///
+/// Keys pinned via `Lru::pin` are not removed for any of the above, except
+/// when the node is deleted or the entry is invalidated.
+///
+/// With `soft_ttl`, entries older than it are flagged stale and the callback
+/// registered via `Lru::on_stale` is fired once for them, they are removed
+/// only after a further `stale_grace`.
+///
+/// With quotas, refer `LruBuilder::quota`, entries of a group beyond its
+/// limits are removed, oldest first.
+///
+/// With `reporter`, a snapshot of cache statistics is reported once every
+/// configured interval, between passes.
 pub(crate) struct Evictor<K> {
-    pub(crate) max_entries: usize,
+    pub(crate) max_entries: Arc<AtomicUsize>,
+    pub(crate) base_entries: usize,
+    pub(crate) adaptive_cap: Option<usize>,
+    pub(crate) pressure: Option<Arc<dyn Pressure>>,
+    pub(crate) pressure_floor: usize,
+    pub(crate) watermarks: Option<(f64, f64)>,
+    pub(crate) reporter: Option<(Duration, Reporter)>,
+    pub(crate) telemetry: Arc<dyn TelemetrySink>,
+    #[cfg(feature = "leak-detect")]
+    pub(crate) leak_threshold: usize,
     pub(crate) max_memory: Option<usize>,
     pub(crate) max_old: Option<Duration>,
+    pub(crate) time_to_idle: Option<Duration>,
+    pub(crate) soft_ttl: Option<Duration>,
+    pub(crate) stale_grace: Duration,
+    pub(crate) protect_young: Option<Duration>,
+    pub(crate) overshoot_band: f64,
+    pub(crate) policy: Policy,
+    pub(crate) freq_decay: Duration,
 
     pub(crate) cur_entries: Arc<AtomicUsize>,
     pub(crate) cur_memory: Arc<AtomicUsize>,
-    pub(crate) n_evicted: usize,
-    pub(crate) n_deleted: usize,
-    pub(crate) n_older: usize,
+    pub(crate) counters: Arc<Counters>,
+    pub(crate) ghost: Option<Arc<Ghost>>,
+    pub(crate) pins: Arc<Pins<K>>,
+    pub(crate) quota: Option<Arc<Quota<K>>>,
 
     pub(crate) list: Arc<list::List<K>>,
     pub(crate) closed: Arc<AtomicBool>,
+    pub(crate) frozen: Arc<AtomicBool>,
+}
+
+// State carried by the evictor across passes.
+struct Walker<K, V, H, M> {
+    map: M,
+    reader: M,
+    hash_builder: H,
+    invalidator: Arc<Invalidator<K, V>>,
+    expiry: Arc<Expiry<K, V>>,
+    handoff: Option<Arc<Handoff<K, V>>>,
+    window: (Instant, usize, usize), // (start, victims, ghost-hits)
+    pacer: Pacer,
+    pressure: (Instant, bool),     // (last-poll, under-pressure)
+    cleared: (u64, Option<usize>), // (watermark, prepends until head is walked)
+    reported: Instant,
+    counters: Arc<Counters>,
+}
+
+impl<K, V, H, M> Walker<K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+{
+    fn new(
+        map: M,
+        hash_builder: H,
+        invalidator: Arc<Invalidator<K, V>>,
+        expiry: Arc<Expiry<K, V>>,
+        handoff: Option<Arc<Handoff<K, V>>>,
+        counters: Arc<Counters>,
+    ) -> Self {
+        Walker {
+            reader: map.cloned(),
+            map,
+            hash_builder,
+            invalidator,
+            expiry,
+            handoff,
+            window: (Instant::now(), 0, 0),
+            pacer: Pacer::new(),
+            pressure: (Instant::now(), false),
+            cleared: (0, None),
+            reported: Instant::now(),
+            counters,
+        }
+    }
+}
+
+// Paces evictor passes from the rate at which entries and memory grow in
+// between passes, refer Evictor::sleep_for.
+struct Pacer {
+    marked: Instant,
+    footprint: (usize, usize), // (entries, memory) left by the last pass.
+    rates: (f64, f64),         // growth per millisecond, smoothed.
+}
+
+impl Pacer {
+    fn new() -> Pacer {
+        Pacer {
+            marked: Instant::now(),
+            footprint: (0, 0),
+            rates: (0.0, 0.0),
+        }
+    }
+
+    // record the footprint left by a pass.
+    fn mark(&mut self, footprint: (usize, usize)) {
+        self.marked = Instant::now();
+        self.footprint = footprint;
+    }
+
+    // fold the growth since the last pass into the smoothed rates.
+    fn observe(&mut self, footprint: (usize, usize)) {
+        let millis = self.marked.elapsed().as_secs_f64() * 1000.0;
+        if millis <= 0.0 {
+            return;
+        }
+        let rate = |now: usize, then: usize, old: f64| {
+            (old + (now.saturating_sub(then) as f64) / millis) / 2.0
+        };
+        self.rates = (
+            rate(footprint.0, self.footprint.0, self.rates.0),
+            rate(footprint.1, self.footprint.1, self.rates.1),
+        );
+    }
+}
+
+// evictor is alive for as long as its walker, including on error or panic.
+impl<K, V, H, M> Drop for Walker<K, V, H, M> {
+    fn drop(&mut self) {
+        self.counters.alive.store(false, SeqCst);
+    }
+}
+
+// rank older entries, visited later in the walk, ahead of newer ones.
+fn to_recency(counts: usize) -> u64 {
+    (u32::MAX - (std::cmp::min(counts, u32::MAX as usize) as u32)) as u64
+}
+
+// return the time, in milliseconds, to consume `headroom` at `rate` per
+// millisecond.
+fn to_millis(headroom: f64, rate: f64) -> f64 {
+    match headroom {
+        headroom if headroom <= 0.0 => 0.0,
+        _ if rate <= 0.0 => MAX_SLEEP,
+        headroom => (headroom / rate).min(MAX_SLEEP),
+    }
+}
+
+// clamp cost to 30 bits, leaving the top bits of a rank for priority.
+fn to_cost(cost: u32) -> u64 {
+    std::cmp::min(cost, (1 << 30) - 1) as u64
 }
 
 impl<K> Evictor<K>
 where
-    K: Clone + PartialEq + Hash,
+    K: Clone + Eq + Hash,
 {
-    pub fn run<V, H>(mut self, mut map: cmap::Map<K, Value<K, V>, H>) -> Result<Self>
+    pub fn run<V, H, M>(
+        self,
+        map: M,
+        hash_builder: H,
+        invalidator: Arc<Invalidator<K, V>>,
+        expiry: Arc<Expiry<K, V>>,
+        handoff: Option<Arc<Handoff<K, V>>>,
+    ) -> Result<Self>
     where
-        V: Clone,
         H: BuildHasher,
+        M: Backend<K, Value<K, V>, H>,
     {
+        let counters = Arc::clone(&self.counters);
+        let mut walker =
+            Walker::new(map, hash_builder, invalidator, expiry, handoff, counters);
+        loop {
+            if self.closed.load(SeqCst) {
+                break;
+            }
+
+            match self.sleep_for(&walker.pacer) {
+                Some(dur) => std::thread::sleep(dur),
+                None => std::thread::yield_now(),
+            }
+
+            walker.pacer.observe(self.footprint());
+            self.pass(&mut walker)?;
+            walker.pacer.mark(self.footprint());
+            self.report(&mut walker);
+            #[cfg(feature = "leak-detect")]
+            self.detect_leaks();
+        }
+
+        Ok(self)
+    }
+
+    /// Same as run, but sleep between passes using tokio's timer, to run the
+    /// evictor as a task. Passes hold the access list's walk lock, so each is
+    /// run on tokio's blocking pool, keeping the runtime's workers free.
+    #[cfg(feature = "async")]
+    pub async fn run_async<V, H, M>(
+        mut self,
+        map: M,
+        hash_builder: H,
+        invalidator: Arc<Invalidator<K, V>>,
+        expiry: Arc<Expiry<K, V>>,
+        handoff: Option<Arc<Handoff<K, V>>>,
+    ) -> Result<Self>
+    where
+        K: 'static + Send,
+        V: 'static + Send,
+        H: 'static + Send + BuildHasher,
+        M: 'static + Backend<K, Value<K, V>, H>,
+    {
+        let counters = Arc::clone(&self.counters);
+        let mut walker =
+            Walker::new(map, hash_builder, invalidator, expiry, handoff, counters);
+        loop {
+            if self.closed.load(SeqCst) {
+                break;
+            }
+
+            match self.sleep_for(&walker.pacer) {
+                Some(dur) => tokio::time::sleep(dur).await,
+                None => tokio::task::yield_now().await,
+            }
+
+            let res = tokio::task::spawn_blocking(move || {
+                walker.pacer.observe(self.footprint());
+                let res = self.pass(&mut walker);
+                walker.pacer.mark(self.footprint());
+                self.report(&mut walker);
+                #[cfg(feature = "leak-detect")]
+                self.detect_leaks();
+                (self, walker, res)
+            });
+            match res.await {
+                Ok((evictor, w, res)) => {
+                    self = evictor;
+                    walker = w;
+                    res?
+                }
+                Err(err) => return err_at!(Fatal, msg: "evictor pass {}", err),
+            }
+        }
+
+        Ok(self)
+    }
+
+    // walk the access list once, removing nodes and entries as they qualify.
+    fn pass<V, H, M>(&self, walker: &mut Walker<K, V, H, M>) -> Result<()>
+    where
+        H: BuildHasher,
+        M: Backend<K, Value<K, V>, H>,
+    {
+        let Walker {
+            map,
+            reader,
+            hash_builder,
+            invalidator,
+            expiry,
+            handoff,
+            window,
+            pressure,
+            cleared,
+            ..
+        } = walker;
+
+        let counters = &self.counters;
+        let key_hash = |key: &K| hash_builder.hash_one(key);
+        let forget = |key: &K| {
+            if let Some(ghost) = self.ghost.as_ref() {
+                ghost.push(key_hash(key));
+            }
+        };
+        let expire = |key: &K, value: Option<Value<K, V>>| {
+            if let Some(value) = value.as_ref() {
+                let n = expiry.fire(key_hash(key), key, value.value.as_ref());
+                counters.n_expire_callbacks.fetch_add(n, SeqCst);
+            }
+            value
+        };
+        // evicted entries are handed over to the consumer, if any, else dropped.
+        let hand_over = |key: &K, value: Option<Value<K, V>>, reason| {
+            if let (Some(handoff), Some(value)) = (handoff.as_ref(), value) {
+                match handoff.send(key.clone(), value.value, reason) {
+                    true => counters.n_handoff.fetch_add(1, SeqCst),
+                    false => counters.n_handoff_dropped.fetch_add(1, SeqCst),
+                };
+            }
+        };
+        let start = Instant::now();
+        let (mut n_scanned, mut n_reclaimed, mut n_removed) = (0, 0, 0);
         let mut remove = |key: &K| match map.remove(key) {
             Some(value) => {
+                n_removed += 1;
                 self.cur_entries.fetch_sub(1, SeqCst);
+                self.cur_memory.fetch_sub(value.weight, SeqCst);
                 unsafe {
                     let ptr = value.access.load(SeqCst);
                     ptr.as_ref().unwrap().delete()
                 };
+                Some(value)
             }
-            None => (),
+            None => None,
+        };
+
+        // lists that fit within the head have nothing to walk, the pass is
+        // still accounted for.
+        let mut empty = None;
+        let _walk = self.list.lock_walk();
+        let mut slot = match self.list.as_mut_head() {
+            Some(list::Node::T { next, .. }) => next,
+            _ => &mut empty,
         };
 
-        let mut n_evicted: usize = 0;
-        let mut n_deleted: usize = 0;
-        let mut n_older: usize = 0;
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        let pass = invalidator.start_pass();
+        let frozen = self.frozen.load(SeqCst);
+        let paused = counters.paused_since.load(SeqCst) > 0;
 
-        loop {
-            if self.closed.load(SeqCst) {
-                break;
-            }
+        let is_idle = |key: &K| match self.time_to_idle {
+            Some(tti) => reader.get_with(key, |v| v.to_idle(now) > tti),
+            None => None,
+        };
 
-            match self.sleep_for() {
-                Some(dur) => std::thread::sleep(dur),
-                None => std::thread::yield_now(),
-            }
+        if pressure.0.elapsed() >= PRESSURE_POLL {
+            *pressure = (Instant::now(), self.is_under_pressure());
+        }
 
-            let prev_node: &mut list::Node<K> = match self.list.as_mut_head() {
-                Some(node) => node,
-                None => continue,
-            };
-            let mut node: &mut list::Node<K> = match prev_node {
-                list::Node::T { next, .. } => next.as_mut().unwrap(),
-                _ => unreachable!(),
-            };
+        let is_dead = |key: &K| match self.soft_ttl {
+            Some(ttl) => reader.get_with(key, |v| v.to_age(now) > ttl + self.stale_grace),
+            None => None,
+        };
+        // flag the entry stale, return its value only the first time.
+        let to_stale = |key: &K| match self.soft_ttl {
+            Some(ttl) => reader.get_with(key, |v| match v.to_age(now) > ttl {
+                true if !v.stale.swap(true, SeqCst) => Some(Arc::clone(&v.value)),
+                _ => None,
+            }),
+            None => None,
+        };
 
-            let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        let is_young = |key: &K| match self.protect_young {
+            Some(age) => reader.get_with(key, |v| v.to_age(now) < age),
+            None => None,
+        };
+        let is_demoted = |key: &K| reader.get_with(key, |v| v.demoted);
+        // entries need looking up only if there are predicates, or if entries
+        // were cleared and are not yet walked. Cleared entries left alone at
+        // the head of the list, past HEAD_SKIP nodes and the one after, are
+        // walked once as many nodes are prepended.
+        let watermark = invalidator.watermark();
+        let n_prepends = self.list.prepends.n_ops.load(SeqCst);
+        if watermark != cleared.0 {
+            *cleared = (watermark, Some(n_prepends + list::HEAD_SKIP + 1));
+        }
+        let invalidating = invalidator.has_preds() || cleared.1.is_some();
+        if cleared.1.is_some_and(|n| n_prepends >= n) {
+            cleared.1 = None;
+        }
+        let is_invalid = |key: &K| match invalidating {
+            true => reader.get_with(key, |v| invalidator.is_invalid(v)),
+            false => None,
+        };
 
-            let mut num_evicts = self.num_evicts();
-            let mut counts = 0;
-            loop {
-                let (key, born, deleted, next) = match node {
-                    list::Node::Z => break,
-                    list::Node::T { key, born, deleted, next } => {
-                        (key, born, deleted, next)
-                    }
-                };
+        let max_entries = match self.max_entries.load(SeqCst) {
+            n if pressure.1 => std::cmp::min(n, self.pressure_floor),
+            n => n,
+        };
+        // with watermarks, evict down to the low mark once above the high mark.
+        let (high, max_entries) = self.to_marks(max_entries);
+        let mut num_evicts = match paused {
+            true => 0,
+            false => self.num_evicts(high, max_entries),
+        };
+        // gdsf victims are picked for memory as well as for count.
+        let mut over_memory = match (self.policy, self.max_memory) {
+            (Policy::Gdsf, Some(m)) if !paused => {
+                self.cur_memory.load(SeqCst).saturating_sub(m)
+            }
+            _ => 0,
+        };
+        // live entries at the head of the list are kept, count them as well.
+        let mut counts = self.list.n_head_live();
+        let mut kept_memory = 0; // footprint of live entries seen so far.
+        let mut candidates: Vec<(u64, K)> = vec![];
+        let mut n_cold = None; // resident cold entries swept, with clock-pro.
+        let mut tally = Tally::default(); // live entries seen so far, per group.
 
-                let node_next: Box<list::Node<K>> = match self.max_old {
-                    _ if deleted.load(SeqCst) => {
-                        n_deleted += 1;
-                        next.take().unwrap()
+        // with cost hints, lru victims are picked by cost, and lfu victims by
+        // frequency, among the oldest entries, twice as many as the number of
+        // victims. With priorities, they are picked among all entries.
+        let prioritized = counters.n_prioritized.load(SeqCst) > 0;
+        let costed = self.policy == Policy::Lru
+            && (prioritized || counters.n_costed.load(SeqCst) > 0);
+        let lru_window = match prioritized {
+            true => 0,
+            false => max_entries.saturating_sub(num_evicts),
+        };
+        while let Some(list::Node::T { key, born, deleted, .. }) = slot.as_deref() {
+            n_scanned += 1;
+
+            let pinned = !self.pins.is_empty()
+                && !deleted.load(SeqCst)
+                && self.pins.is_pinned(key_hash(key), key);
+            // spared as count-limit, size-limit and quota victim.
+            let spared =
+                pinned || (!deleted.load(SeqCst) && is_young(key).unwrap_or(false));
+            // group and footprint of the entry, with quotas.
+            let group = match self.quota.as_ref() {
+                Some(quota) if !deleted.load(SeqCst) => {
+                    reader.get_with(key, |v| (quota.classify(key), v.weight))
+                }
+                _ => None,
+            };
+            let unlink = match self.max_old {
+                _ if deleted.load(SeqCst) => {
+                    counters.n_deleted.fetch_add(1, SeqCst);
+                    n_reclaimed += 1;
+                    true
+                }
+                _ if frozen => {
+                    counts += 1;
+                    false
+                }
+                _ if self.policy == Policy::Lru
+                    && !costed
+                    && !spared
+                    && num_evicts > 0
+                    && (counts >= max_entries || is_demoted(key).unwrap_or(false)) =>
+                {
+                    hand_over(key, remove(key), EvictionReason::Capacity);
+                    forget(key);
+                    counters.n_older.fetch_add(1, SeqCst);
+                    window.1 += 1;
+                    num_evicts -= 1;
+                    true
+                }
+                _ if !spared
+                    && !paused
+                    && self.policy != Policy::Gdsf
+                    && self.max_memory.is_some_and(|m| kept_memory > m) =>
+                {
+                    hand_over(key, remove(key), EvictionReason::Memory);
+                    forget(key);
+                    counters.n_older.fetch_add(1, SeqCst);
+                    window.1 += 1;
+                    true
+                }
+                _ if !spared
+                    && !paused
+                    && group.is_some_and(|(group, weight)| {
+                        let quota = self.quota.as_ref().unwrap();
+                        quota.is_over(&tally, group, weight)
+                    }) =>
+                {
+                    hand_over(key, remove(key), EvictionReason::Quota);
+                    counters.n_quota.fetch_add(1, SeqCst);
+                    true
+                }
+                Some(max_old) if !pinned && (now - *born) > max_old => {
+                    hand_over(key, expire(key, remove(key)), EvictionReason::Expired);
+                    counters.n_older.fetch_add(1, SeqCst);
+                    true
+                }
+                _ if !pinned && is_idle(key).unwrap_or(false) => {
+                    hand_over(key, expire(key, remove(key)), EvictionReason::Expired);
+                    counters.n_older.fetch_add(1, SeqCst);
+                    true
+                }
+                _ if !pinned && is_dead(key).unwrap_or(false) => {
+                    hand_over(key, expire(key, remove(key)), EvictionReason::Expired);
+                    counters.n_older.fetch_add(1, SeqCst);
+                    true
+                }
+                _ if is_invalid(key).unwrap_or(false) => {
+                    hand_over(key, remove(key), EvictionReason::Invalidated);
+                    counters.n_invalidated.fetch_add(1, SeqCst);
+                    true
+                }
+                _ => {
+                    // callback is fired outside the map lookup, it may set
+                    // the key afresh.
+                    if let Some(Some(value)) = to_stale(key) {
+                        counters.n_stale.fetch_add(1, SeqCst);
+                        expiry.fire_stale(key, value.as_ref());
                     }
-                    _ if counts > self.max_entries && num_evicts > 0 => {
-                        remove(key);
-                        n_older += 1;
-                        num_evicts -= 1;
-                        next.take().unwrap()
+                    if self.policy == Policy::ClockPro && !spared && num_evicts > 0 {
+                        // older entries are visited later, and picked first.
+                        let recency = to_recency(counts);
+                        let rank = reader.get_with(key, |v| {
+                            let rank = match v.demoted {
+                                true => Some(0),
+                                false if v.sweep() => Some(v.to_rank(recency)),
+                                false => None,
+                            };
+                            (rank, v.clock.load(SeqCst) & crate::CLOCK_HOT == 0)
+                        });
+                        if let Some((rank, cold)) = rank {
+                            n_cold = Some(n_cold.unwrap_or(0) + (cold as usize));
+                            if let Some(rank) = rank {
+                                candidates.push((rank, key.clone()));
+                            }
+                        }
                     }
-                    Some(max_old) if (now - *born) > max_old => {
-                        remove(key);
-                        n_older += 1;
-                        next.take().unwrap()
+                    if self.policy == Policy::Gdsf
+                        && !spared
+                        && (num_evicts > 0 || over_memory > 0)
+                    {
+                        let epoch = crate::freq_epoch(now, self.freq_decay);
+                        // non-negative floats order as their bits do.
+                        let rank = reader.get_with(key, |v| match v.demoted {
+                            true => 0,
+                            false => v.to_rank(v.to_gdsf(epoch).to_bits() >> 2),
+                        });
+                        if let Some(rank) = rank {
+                            candidates.push((rank, key.clone()));
+                        }
                     }
-                    _ => {
-                        node = next.as_mut().unwrap();
-                        counts += 1;
-                        continue;
+                    if costed && !spared && num_evicts > 0 && counts >= lru_window {
+                        // cheaper first, older first among the same cost.
+                        let recency = to_recency(counts);
+                        let rank = reader.get_with(key, |v| match v.demoted {
+                            true => 0,
+                            false => v.to_rank((to_cost(v.cost) << 32) | recency),
+                        });
+                        if let Some(rank) = rank {
+                            candidates.push((rank, key.clone()));
+                        }
                     }
-                };
-
-                n_evicted += 1;
+                    if self.policy == Policy::Lfu
+                        && !spared
+                        && num_evicts > 0
+                        && counts >= lru_window
+                    {
+                        let epoch = crate::freq_epoch(now, self.freq_decay);
+                        let freq = reader.get_with(key, |v| match v.demoted {
+                            true => 0,
+                            false => {
+                                v.to_rank((v.to_freq(epoch) << 32) | (v.cost as u64))
+                            }
+                        });
+                        if let Some(freq) = freq {
+                            candidates.push((freq, key.clone()));
+                        }
+                    }
+                    if let Some((group, weight)) = group {
+                        tally.keep(group, weight);
+                    }
+                    if self.max_memory.is_some() {
+                        kept_memory += reader.get_with(key, |v| v.weight).unwrap_or(0);
+                    }
+                    counts += 1;
+                    false
+                }
+            };
 
-                let _drop_node = match prev_node {
-                    list::Node::T { next, .. } => next.replace(node_next),
+            if unlink {
+                counters.n_evicted.fetch_add(1, SeqCst);
+                let mut node = slot.take().unwrap();
+                *slot = match node.as_mut() {
+                    list::Node::T { next, .. } => next.take(),
+                    list::Node::Z => unreachable!(),
+                };
+                self.list.free(Some(node));
+            } else {
+                slot = match slot.as_deref_mut() {
+                    Some(list::Node::T { next, .. }) => next,
                     _ => unreachable!(),
                 };
+            }
+        }
 
-                node = match prev_node {
-                    list::Node::T { next, .. } => next.as_mut().unwrap(),
-                    _ => unreachable!(),
+        // with clock-pro, non-resident entries are bounded by resident cold
+        // ones, ghost is shared with adaptive_cap.
+        if let (Some(n_cold), Some(ghost)) = (n_cold, self.ghost.as_ref()) {
+            let n =
+                self.adaptive_cap.map_or(0, |cap| cap.saturating_sub(self.base_entries));
+            ghost.resize(std::cmp::max(n_cold, n));
+        }
+
+        // access nodes of costed lru, lfu, clock-pro and gdsf victims are
+        // reclaimed in the next pass.
+        candidates.sort_by_key(|(rank, _)| *rank);
+        for (_, key) in candidates.iter() {
+            if num_evicts == 0 && over_memory == 0 {
+                break;
+            }
+            let reason = match num_evicts {
+                0 => EvictionReason::Memory,
+                _ => EvictionReason::Capacity,
+            };
+            if let Some(value) = remove(key) {
+                over_memory = over_memory.saturating_sub(value.weight);
+                hand_over(key, Some(value), reason);
+            }
+            forget(key);
+            counters.n_older.fetch_add(1, SeqCst);
+            window.1 += 1;
+            num_evicts = num_evicts.saturating_sub(1);
+        }
+
+        // nodes left alone at the head are not walked, apply predicates to them
+        // before the predicates are dropped.
+        if invalidator.has_preds() {
+            for key in self.list.to_head_keys() {
+                if is_invalid(&key).unwrap_or(false) {
+                    hand_over(&key, remove(&key), EvictionReason::Invalidated);
+                    counters.n_invalidated.fetch_add(1, SeqCst);
                 }
             }
         }
+        invalidator.finish_pass(pass);
 
-        self.n_evicted = n_evicted;
-        self.n_deleted = n_deleted;
-        self.n_older = n_older;
+        let stats = PassStats {
+            n_passes: 1,
+            n_scanned,
+            n_reclaimed,
+            n_evicted: n_removed,
+            elapsed: start.elapsed(),
+        };
+        counters.passes.add(&stats);
+        counters.last_pass_stats.store(&stats);
 
-        Ok(self)
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        counters.last_pass.store(now.as_micros() as u64, SeqCst);
+
+        if window.0.elapsed() >= ADAPT_WINDOW {
+            // ghost-hits can go down if stats are reset.
+            let n_hits = counters.n_ghost_hits.load(SeqCst);
+            self.adapt(window.1, n_hits.saturating_sub(window.2));
+            *window = (Instant::now(), 0, n_hits);
+        }
+
+        Ok(())
+    }
+
+    // invoke the stats reporter, if any, once every configured interval.
+    fn report<V, H, M>(&self, walker: &mut Walker<K, V, H, M>) {
+        if let Some((interval, reporter)) = self.reporter.as_ref() {
+            if walker.reported.elapsed() >= *interval {
+                let (counters, max_entries) = (&self.counters, &self.max_entries);
+                reporter(to_stats_with(counters, &self.list, max_entries, |c| {
+                    c.load(SeqCst)
+                }));
+                walker.reported = Instant::now();
+            }
+        }
     }
 
-    fn sleep_for(&self) -> Option<Duration> {
-        use std::cmp::Ordering;
+    // compare the number of access nodes allocated, and not yet freed, with
+    // the number of entries plus tombstones in the access list, a difference
+    // beyond `leak_threshold` is a sign of nodes leaking out of the list.
+    #[cfg(feature = "leak-detect")]
+    fn detect_leaks(&self) {
+        let mut n_tombstones = 0;
+        self.list.walk(|node| {
+            n_tombstones += if node.is_deleted() { 1 } else { 0 };
+            true
+        });
 
-        let entries = self.cur_entries.load(SeqCst);
-        let memory = self.cur_memory.load(SeqCst);
+        let n_nodes = self.list.n_nodes.load(SeqCst);
+        let expected = self.cur_entries.load(SeqCst) + n_tombstones;
+        let diff = std::cmp::max(n_nodes, expected) - std::cmp::min(n_nodes, expected);
+        if diff > self.leak_threshold {
+            self.counters.n_leak_alerts.fetch_add(1, SeqCst);
+            self.telemetry.event(Event::Leak { n_nodes, expected });
+        }
+    }
 
-        let ratio1 = (entries as f64) / (self.max_entries as f64);
-        let ratio2 = match self.max_memory.clone() {
-            Some(max_memory) => (memory as f64) / (max_memory as f64),
-            None => 0.0,
+    // adapt max_entries based on the ghost-hits to victims ratio in the last
+    // window.
+    fn adapt(&self, n_victims: usize, n_hits: usize) {
+        let cap = match self.adaptive_cap {
+            Some(cap) => cap,
+            None => return,
         };
 
-        let ratio = match ratio1.total_cmp(&ratio2) {
-            Ordering::Less => ratio2,
-            _ => ratio1,
-        };
+        let max_entries = self.max_entries.load(SeqCst);
+        let step = std::cmp::max(self.base_entries / ADAPT_STEP, 1);
+
+        if n_victims > 0 && (n_hits * GROW_RATIO) >= n_victims && max_entries < cap {
+            let n = std::cmp::min(max_entries + step, cap);
+            self.max_entries.store(n, SeqCst);
+            self.counters.n_grows.fetch_add(1, SeqCst);
+            let (from, to) = (max_entries, n);
+            self.telemetry.event(Event::Grow { from, to, n_victims, n_hits });
+        } else if (n_hits * SHRINK_RATIO) < n_victims && max_entries > self.base_entries {
+            let n = std::cmp::max(max_entries.saturating_sub(step), self.base_entries);
+            self.max_entries.store(n, SeqCst);
+            self.counters.n_shrinks.fetch_add(1, SeqCst);
+            let (from, to) = (max_entries, n);
+            self.telemetry.event(Event::Shrink { from, to, n_victims, n_hits });
+        }
+    }
+
+    // sleep for as long as entries, and memory, take to grow past their
+    // limits by `overshoot_band`, at the rate they grew in between passes.
+    // Yield if they are already past, and back off to MAX_SLEEP when idle.
+    fn sleep_for(&self, pacer: &Pacer) -> Option<Duration> {
+        let band = 1.0 + self.overshoot_band;
+        let (entries, memory) = self.footprint();
+
+        let (high, _) = self.to_marks(self.max_entries.load(SeqCst));
+        let headroom = (high as f64) * band - (entries as f64);
+        let mut millis = to_millis(headroom, pacer.rates.0);
+        if let Some(max_memory) = self.max_memory {
+            let headroom = (max_memory as f64) * band - (memory as f64);
+            millis = millis.min(to_millis(headroom, pacer.rates.1));
+        }
 
-        match ratio {
-            ratio if ratio >= 1.0 => None,
-            ratio => Some(Duration::from_millis((MAX_SLEEP * (1.0 - ratio)) as u64)),
+        match millis {
+            millis if millis < MIN_SLEEP => None,
+            millis => Some(Duration::from_secs_f64(millis / 1000.0)),
         }
     }
 
-    fn num_evicts(&self) -> usize {
-        let a = self.cur_entries.load(SeqCst);
-        if self.max_entries < a {
-            a - self.max_entries
-        } else {
-            0
+    // return the number of entries and their memory footprint.
+    fn footprint(&self) -> (usize, usize) {
+        (self.cur_entries.load(SeqCst), self.cur_memory.load(SeqCst))
+    }
+
+    fn num_evicts(&self, high: usize, low: usize) -> usize {
+        match self.cur_entries.load(SeqCst) {
+            a if a > high => a.saturating_sub(low),
+            _ => 0,
+        }
+    }
+
+    // return the (high, low) count-limits for `max_entries`.
+    fn to_marks(&self, max_entries: usize) -> (usize, usize) {
+        match self.watermarks {
+            Some((high, low)) => {
+                let high = ((max_entries as f64) * high) as usize;
+                let low = ((max_entries as f64) * low) as usize;
+                (high, std::cmp::min(low, high))
+            }
+            None => (max_entries, max_entries),
+        }
+    }
+
+    fn is_under_pressure(&self) -> bool {
+        match self.pressure.as_ref() {
+            Some(pressure) if pressure.is_under_pressure() => {
+                self.counters.n_pressure.fetch_add(1, SeqCst);
+                let floor = self.pressure_floor;
+                self.telemetry.event(Event::Pressure { floor });
+                true
+            }
+            _ => false,
         }
     }
 }
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, Mutex};
+
+type Callback<K, V> = Box<dyn FnOnce(&K, &V) + Send>;
+type OnStale<K, V> = Arc<dyn Fn(&K, &V) + Send + Sync>;
+type Callbacks<K, V> = HashMap<u64, Vec<(K, Callback<K, V>)>>;
+
+/// Callbacks registered via `Lru::on_expire`, fired by the evictor when the
+/// key expires due to `max_old`, `time_to_idle` or `soft_ttl`. Callbacks are
+/// not fired when the key is evicted for capacity, or removed explicitly.
+///
+/// Callbacks are indexed by key's hash, computed by the caller.
+///
+/// Additionally holds the callback registered via `Lru::on_stale`, fired by
+/// the evictor once per entry that is past `soft_ttl`.
+pub(crate) struct Expiry<K, V> {
+    callbacks: Mutex<Callbacks<K, V>>,
+    n_callbacks: AtomicUsize,
+    on_stale: Mutex<Option<OnStale<K, V>>>,
+}
+
+impl<K, V> Default for Expiry<K, V> {
+    fn default() -> Expiry<K, V> {
+        Expiry {
+            callbacks: Mutex::new(HashMap::new()),
+            n_callbacks: AtomicUsize::new(0),
+            on_stale: Mutex::new(None),
+        }
+    }
+}
+
+impl<K, V> Expiry<K, V>
+where
+    K: PartialEq,
+{
+    pub fn register(&self, hash: u64, key: K, callb: Callback<K, V>) {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        callbacks.entry(hash).or_default().push((key, callb));
+        self.n_callbacks.fetch_add(1, SeqCst);
+    }
+
+    /// Drop callbacks registered for `key`, return the number of callbacks.
+    pub fn cancel(&self, hash: u64, key: &K) -> usize {
+        self.take(hash, key).len()
+    }
+
+    /// Fire and drop callbacks registered for the expired `key`.
+    pub fn fire(&self, hash: u64, key: &K, value: &V) -> usize {
+        if self.n_callbacks.load(SeqCst) == 0 {
+            return 0;
+        }
+
+        let callbs = self.take(hash, key);
+        let n = callbs.len();
+        for callb in callbs.into_iter() {
+            callb(key, value)
+        }
+        n
+    }
+
+    pub fn set_on_stale(&self, callb: Option<OnStale<K, V>>) {
+        *self.on_stale.lock().unwrap() = callb;
+    }
+
+    /// Fire the stale callback, if any, for `key`.
+    pub fn fire_stale(&self, key: &K, value: &V) {
+        let callb = self.on_stale.lock().unwrap().clone();
+        if let Some(callb) = callb {
+            callb(key, value)
+        }
+    }
+
+    fn take(&self, hash: u64, key: &K) -> Vec<Callback<K, V>> {
+        let mut callbacks = self.callbacks.lock().unwrap();
+
+        let mut callbs = vec![];
+        if let Some(entries) = callbacks.get_mut(&hash) {
+            let mut i = 0;
+            while i < entries.len() {
+                match &entries[i].0 == key {
+                    true => callbs.push(entries.remove(i).1),
+                    false => i += 1,
+                }
+            }
+            if entries.is_empty() {
+                callbacks.remove(&hash);
+            }
+        }
+
+        self.n_callbacks.fetch_sub(callbs.len(), SeqCst);
+        callbs
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::Ordering::SeqCst;
+use std::time::UNIX_EPOCH;
+
+use crate::{invalidate::Invalidator, list::List, Backend, Error, Result, Value};
+
+/// One line of a JSON-lines dump, refer `Lru::export_jsonl`, also the record
+/// of a snapshot, refer `Lru::save`.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Record<K, V> {
+    pub key: K,
+    pub value: V,
+    /// time elapsed since the entry was inserted, in milliseconds.
+    pub age_ms: u64,
+    /// number of gets on the entry.
+    pub hits: usize,
+}
+
+/// Invoke `callb` with a record for each valid entry, least recently used
+/// first, return the number of records. Entries are read without recording
+/// an access.
+pub(crate) fn walk_records<K, V, H, M>(
+    list: &List<K>,
+    map: &M,
+    invalidator: &Invalidator<K, V>,
+    callb: &mut dyn FnMut(&Record<K, V>) -> Result<()>,
+) -> Result<usize>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher,
+    M: Backend<K, Value<K, V>, H>,
+{
+    let mut keys = vec![];
+    list.walk(|node| {
+        if !node.is_deleted() {
+            keys.push(node.to_key().clone())
+        }
+        true
+    });
+
+    let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+    let mut n = 0;
+    while let Some(key) = keys.pop() {
+        let record = map.get_with(&key, |value: &Value<K, V>| {
+            match invalidator.is_invalid(value) {
+                true => None,
+                false => Some(Record {
+                    key: key.clone(),
+                    value: value.value.as_ref().clone(),
+                    age_ms: value.to_age(now).as_millis() as u64,
+                    hits: value.n_access.load(SeqCst),
+                }),
+            }
+        });
+        if let Some(Some(record)) = record {
+            callb(&record)?;
+            n += 1;
+        }
+    }
+
+    Ok(n)
+}
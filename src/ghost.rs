@@ -0,0 +1,78 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering::SeqCst},
+    Mutex,
+};
+
+// hash to its number of copies in the FIFO, and whether it is still live.
+type Index = HashMap<u64, (usize, bool)>;
+
+/// Ghost list remembers the hash of recently evicted keys, without their values.
+///
+/// A set on a key that is still in the ghost list is a ghost-hit, indicating
+/// the key would have been a cache-hit had the cache been larger.
+pub struct Ghost {
+    capacity: AtomicUsize,
+    // FIFO of hashes, and their index. Hits only clear the live flag, copies
+    // are dropped as they fall off the FIFO, keeping hit and push O(1).
+    hashes: Mutex<(VecDeque<u64>, Index)>,
+}
+
+impl Ghost {
+    pub fn new(capacity: usize) -> Ghost {
+        let hashes = (VecDeque::new(), HashMap::new());
+        Ghost {
+            capacity: AtomicUsize::new(std::cmp::max(capacity, 1)),
+            hashes: Mutex::new(hashes),
+        }
+    }
+
+    /// Remember an evicted key's hash, forgetting the oldest one when full.
+    pub fn push(&self, hash: u64) {
+        let mut guard = self.hashes.lock().unwrap();
+        let (fifo, index) = &mut *guard;
+
+        let (n, live) = index.entry(hash).or_insert((0, false));
+        if !*live {
+            *n += 1;
+            *live = true;
+            fifo.push_back(hash);
+        }
+        Ghost::trim(fifo, index, self.capacity.load(SeqCst));
+    }
+
+    /// Remember up to `capacity` hashes, forgetting the oldest ones.
+    pub fn resize(&self, capacity: usize) {
+        let capacity = std::cmp::max(capacity, 1);
+        self.capacity.store(capacity, SeqCst);
+
+        let mut guard = self.hashes.lock().unwrap();
+        let (fifo, index) = &mut *guard;
+        Ghost::trim(fifo, index, capacity);
+    }
+
+    fn trim(fifo: &mut VecDeque<u64>, index: &mut Index, n: usize) {
+        while fifo.len() > n {
+            let old = fifo.pop_front().unwrap();
+            match index.get_mut(&old) {
+                Some((n, _)) if *n > 1 => *n -= 1,
+                _ => {
+                    index.remove(&old);
+                }
+            }
+        }
+    }
+
+    /// Return whether `hash` is in the ghost list. Ghost entries are counted
+    /// only once and forgotten on hit.
+    pub fn hit(&self, hash: u64) -> bool {
+        let mut guard = self.hashes.lock().unwrap();
+        match guard.1.get_mut(&hash) {
+            Some((_, live)) if *live => {
+                *live = false;
+                true
+            }
+            _ => false,
+        }
+    }
+}
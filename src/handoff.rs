@@ -0,0 +1,62 @@
+use std::sync::{mpsc, Arc};
+
+/// Why an entry was evicted, refer [crate::LruBuilder::evict_to].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvictionReason {
+    /// evicted as cache exceeds `max_entries`, or host is under pressure.
+    Capacity,
+    /// evicted as cache exceeds `max_memory`.
+    Memory,
+    /// evicted as entry's group exceeds its quota.
+    Quota,
+    /// expired due to `max_old`, `time_to_idle` or `soft_ttl` plus
+    /// `stale_grace`.
+    Expired,
+    /// invalidated via [crate::Lru::invalidate_entries_if] and friends.
+    Invalidated,
+}
+
+/// What the evictor does with an evicted entry when the handoff channel is
+/// full, refer [crate::LruBuilder::evict_to].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HandoffFull {
+    /// Drop the entry, counted as `n_handoff_dropped`.
+    Drop,
+    /// Block the evictor until the consumer catches up.
+    Block,
+}
+
+type Sender<K, V> = Box<dyn Fn(K, Arc<V>, EvictionReason) -> bool + Send + Sync>;
+
+/// Hands over evicted entries to a consumer, instead of dropping them. Built
+/// via [crate::LruBuilder::evict_to].
+pub struct Handoff<K, V> {
+    send: Sender<K, V>,
+}
+
+impl<K, V> Handoff<K, V> {
+    pub(crate) fn new(
+        tx: mpsc::SyncSender<(K, V, EvictionReason)>,
+        full: HandoffFull,
+    ) -> Self
+    where
+        K: 'static + Send,
+        V: 'static + Send + Clone,
+    {
+        let send: Sender<K, V> = Box::new(move |key, value, reason| {
+            // value is cloned only if shared, say by a set_dedup.
+            let value = Arc::try_unwrap(value).unwrap_or_else(|v| v.as_ref().clone());
+            match full {
+                HandoffFull::Drop => tx.try_send((key, value, reason)).is_ok(),
+                HandoffFull::Block => tx.send((key, value, reason)).is_ok(),
+            }
+        });
+        Handoff { send }
+    }
+
+    /// Send an evicted entry to the consumer, return false if it is dropped,
+    /// because the channel is full or the consumer is gone.
+    pub(crate) fn send(&self, key: K, value: Arc<V>, reason: EvictionReason) -> bool {
+        (self.send)(key, value, reason)
+    }
+}
@@ -0,0 +1,186 @@
+//! Module implement [HeapSize] to estimate the memory footprint of entries.
+//!
+//! Footprint of an entry is used for `max_memory` accounting, when the cache
+//! is built using `LruBuilder::build_heap_sized`. Use `#[derive(HeapSize)]`,
+//! from the `derive` feature, for user defined types.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::{mem, path, rc::Rc, sync::Arc, time};
+
+/// Trait to estimate the memory owned by a value, including heap allocations
+/// behind pointers.
+pub trait HeapSize {
+    /// Return the heap memory owned by this value, excluding `size_of::<Self>()`.
+    fn heap_size(&self) -> usize;
+
+    /// Return the total memory footprint of this value.
+    fn footprint(&self) -> usize
+    where
+        Self: Sized,
+    {
+        mem::size_of::<Self>() + self.heap_size()
+    }
+}
+
+macro_rules! impl_zero_heap_size {
+    ($($t:ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+impl_zero_heap_size!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    &'static str,
+    time::Duration,
+    time::Instant,
+    time::SystemTime
+);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for path::PathBuf {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        let items: usize = self.iter().map(HeapSize::heap_size).sum();
+        (self.capacity() * mem::size_of::<T>()) + items
+    }
+}
+
+impl<T: HeapSize> HeapSize for VecDeque<T> {
+    fn heap_size(&self) -> usize {
+        let items: usize = self.iter().map(HeapSize::heap_size).sum();
+        (self.capacity() * mem::size_of::<T>()) + items
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::heap_size).sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map(HeapSize::heap_size).unwrap_or(0)
+    }
+}
+
+impl<T: HeapSize, E: HeapSize> HeapSize for std::result::Result<T, E> {
+    fn heap_size(&self) -> usize {
+        match self {
+            Ok(val) => val.heap_size(),
+            Err(err) => err.heap_size(),
+        }
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().footprint()
+    }
+}
+
+// shared values are accounted in full against every owner.
+impl<T: HeapSize> HeapSize for Arc<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().footprint()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Rc<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().footprint()
+    }
+}
+
+// hash tables are approximated to one control byte per bucket.
+impl<K: HeapSize, V: HeapSize, S> HeapSize for HashMap<K, V, S> {
+    fn heap_size(&self) -> usize {
+        let items: usize = self.iter().map(|(k, v)| k.heap_size() + v.heap_size()).sum();
+        (self.capacity() * (mem::size_of::<(K, V)>() + 1)) + items
+    }
+}
+
+impl<T: HeapSize, S> HeapSize for HashSet<T, S> {
+    fn heap_size(&self) -> usize {
+        let items: usize = self.iter().map(HeapSize::heap_size).sum();
+        (self.capacity() * (mem::size_of::<T>() + 1)) + items
+    }
+}
+
+// b-tree nodes are approximated to the size of their items.
+impl<K: HeapSize, V: HeapSize> HeapSize for BTreeMap<K, V> {
+    fn heap_size(&self) -> usize {
+        let items: usize = self.iter().map(|(k, v)| k.footprint() + v.footprint()).sum();
+        items
+    }
+}
+
+impl<T: HeapSize> HeapSize for BTreeSet<T> {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::footprint).sum()
+    }
+}
+
+macro_rules! impl_tuple_heap_size {
+    ($(($($t:ident: $i:tt),+)),*) => {
+        $(
+            impl<$($t: HeapSize),+> HeapSize for ($($t,)+) {
+                fn heap_size(&self) -> usize {
+                    0 $(+ self.$i.heap_size())+
+                }
+            }
+        )*
+    };
+}
+
+impl_tuple_heap_size!(
+    (A: 0),
+    (A: 0, B: 1),
+    (A: 0, B: 1, C: 2),
+    (A: 0, B: 1, C: 2, D: 3)
+);
+
+// weigher used when entries are not HeapSize, accounts only the inline size.
+pub(crate) fn shallow_weight<K, V>(_key: &K, _value: &V) -> usize {
+    mem::size_of::<K>() + mem::size_of::<V>()
+}
+
+pub(crate) fn heap_weight<K, V>(key: &K, value: &V) -> usize
+where
+    K: HeapSize,
+    V: HeapSize,
+{
+    key.footprint() + value.footprint()
+}
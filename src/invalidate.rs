@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, RwLock};
+
+use crate::Value;
+
+type Predicate<K, V> = Arc<dyn Fn(&K, &V) -> bool + Send + Sync>;
+type Registered<K, V> = (u64, u64, Predicate<K, V>); // (version, pass, predicate)
+
+/// Invalidator holds predicates registered via `Lru::invalidate_entries_if`,
+/// and the version upto which entries are cleared via `Lru::clear`.
+///
+/// A predicate applies only to entries inserted before it was registered. It
+/// is applied lazily, by get on the entries it looks up and by the evictor on
+/// the entries it walks through, and dropped once a full evictor pass has
+/// started and finished after its registration.
+pub(crate) struct Invalidator<K, V> {
+    preds: RwLock<Vec<Registered<K, V>>>,
+    n_preds: AtomicUsize,
+    // entries upto this version are cleared, refer Lru::clear.
+    cleared: AtomicU64,
+    n_passes: AtomicU64,
+}
+
+impl<K, V> Default for Invalidator<K, V> {
+    fn default() -> Invalidator<K, V> {
+        Invalidator {
+            preds: RwLock::new(vec![]),
+            n_preds: AtomicUsize::new(0),
+            cleared: AtomicU64::new(0),
+            n_passes: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K, V> Invalidator<K, V> {
+    /// Register predicate, applicable to entries upto `version`.
+    pub fn register(&self, version: u64, pred: Predicate<K, V>) {
+        let mut preds = self.preds.write().unwrap();
+        preds.push((version, self.n_passes.load(SeqCst), pred));
+        self.n_preds.store(preds.len(), SeqCst);
+    }
+
+    /// Invalidate all entries upto `version`, without a predicate.
+    pub fn clear(&self, version: u64) {
+        self.cleared.fetch_max(version, SeqCst);
+    }
+
+    /// Return whether the entry is cleared, or invalidated by any of the
+    /// predicates.
+    pub fn is_invalid(&self, value: &Value<K, V>) -> bool {
+        let version = value.version.load(SeqCst);
+        if version <= self.cleared.load(SeqCst) {
+            return true;
+        } else if self.n_preds.load(SeqCst) == 0 {
+            return false;
+        }
+
+        let key = &value.key;
+        let preds = self.preds.read().unwrap();
+        preds.iter().any(|(v, _, pred)| version <= *v && pred(key, &value.value))
+    }
+
+    /// Return the version upto which entries are cleared.
+    pub fn watermark(&self) -> u64 {
+        self.cleared.load(SeqCst)
+    }
+
+    /// Return whether any predicate is pending.
+    pub fn has_preds(&self) -> bool {
+        self.n_preds.load(SeqCst) > 0
+    }
+
+    /// Start an evictor pass, return its sequence number.
+    pub fn start_pass(&self) -> u64 {
+        self.n_passes.fetch_add(1, SeqCst) + 1
+    }
+
+    /// Finish an evictor pass, dropping predicates registered before it started.
+    pub fn finish_pass(&self, pass: u64) {
+        if self.n_preds.load(SeqCst) > 0 {
+            let mut preds = self.preds.write().unwrap();
+            preds.retain(|(_, p, _)| *p >= pass);
+            self.n_preds.store(preds.len(), SeqCst);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Keys locked via `Lru::lock_key`, held exclusively by one caller at a time,
+/// whether or not the key is cached.
+///
+/// Locks are advisory, only lock_key and try_lock_key honor them, they don't
+/// block gets and sets. Locks are indexed by key's hash, computed by the
+/// caller.
+pub(crate) struct KeyLocks<K> {
+    locks: Mutex<HashMap<u64, Vec<K>>>,
+    cond: Condvar,
+}
+
+impl<K> Default for KeyLocks<K> {
+    fn default() -> KeyLocks<K> {
+        KeyLocks {
+            locks: Mutex::new(HashMap::new()),
+            cond: Condvar::new(),
+        }
+    }
+}
+
+impl<K> KeyLocks<K>
+where
+    K: PartialEq,
+{
+    /// Lock `key`, blocking until its current holder, if any, unlocks it.
+    pub fn lock(&self, hash: u64, key: K) {
+        let mut locks = self.locks.lock().unwrap();
+        while Self::is_locked(&locks, hash, &key) {
+            locks = self.cond.wait(locks).unwrap();
+        }
+        locks.entry(hash).or_default().push(key);
+    }
+
+    /// Lock `key`, return false if it is already locked.
+    pub fn try_lock(&self, hash: u64, key: K) -> bool {
+        let mut locks = self.locks.lock().unwrap();
+        if Self::is_locked(&locks, hash, &key) {
+            return false;
+        }
+        locks.entry(hash).or_default().push(key);
+        true
+    }
+
+    pub fn unlock(&self, hash: u64, key: &K) {
+        let mut locks = self.locks.lock().unwrap();
+        if let Some(keys) = locks.get_mut(&hash) {
+            keys.retain(|k| k != key);
+            if keys.is_empty() {
+                locks.remove(&hash);
+            }
+        }
+        // waiters on other keys of the same hash recheck and wait again.
+        self.cond.notify_all();
+    }
+
+    fn is_locked(locks: &HashMap<u64, Vec<K>>, hash: u64, key: &K) -> bool {
+        match locks.get(&hash) {
+            Some(keys) => keys.iter().any(|k| k == key),
+            None => false,
+        }
+    }
+}
+
+/// Guard returned by [crate::Lru::lock_key], the key stays locked for as long
+/// as the guard is alive, and is unlocked when the guard is dropped, including
+/// while unwinding from a panic.
+pub struct KeyGuard<K>
+where
+    K: PartialEq,
+{
+    locks: Arc<KeyLocks<K>>,
+    hash: u64,
+    key: K,
+}
+
+impl<K> KeyGuard<K>
+where
+    K: PartialEq,
+{
+    pub(crate) fn new(locks: Arc<KeyLocks<K>>, hash: u64, key: K) -> KeyGuard<K> {
+        KeyGuard { locks, hash, key }
+    }
+
+    /// Return the locked key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K> Drop for KeyGuard<K>
+where
+    K: PartialEq,
+{
+    fn drop(&mut self) {
+        self.locks.unlock(self.hash, &self.key);
+    }
+}
@@ -1,3 +1,11 @@
+//! Concurrent LRU cache.
+//!
+//! [Lru] is the cache, configured and built via [LruBuilder]. Entries are
+//! held in a sharded concurrent map, refer [Backend], and their recency is
+//! tracked in a single lock-free access list walked by a background evictor,
+//! that enforces entry-count and memory limits. Cache handles are cloned for
+//! each thread, refer [Lru::stats] for statistics.
+
 #![feature(total_cmp)]
 
 use std::{error, fmt, result};
@@ -54,6 +62,13 @@ macro_rules! err_at {
 /// error location.
 pub enum Error {
     Fatal(String, String),
+    VersionMismatch(String, String),
+    Rejected(String, String),
+    Overflow(String, String),
+    IOError(String, String),
+    FailConvert(String, String),
+    Frozen(String, String),
+    Oversized(String, String),
 }
 
 impl fmt::Display for Error {
@@ -62,6 +77,13 @@ impl fmt::Display for Error {
 
         match self {
             Fatal(p, msg) => write!(f, "{} Fatal: {}", p, msg),
+            VersionMismatch(p, msg) => write!(f, "{} VersionMismatch: {}", p, msg),
+            Rejected(p, msg) => write!(f, "{} Rejected: {}", p, msg),
+            Overflow(p, msg) => write!(f, "{} Overflow: {}", p, msg),
+            IOError(p, msg) => write!(f, "{} IOError: {}", p, msg),
+            FailConvert(p, msg) => write!(f, "{} FailConvert: {}", p, msg),
+            Frozen(p, msg) => write!(f, "{} Frozen: {}", p, msg),
+            Oversized(p, msg) => write!(f, "{} Oversized: {}", p, msg),
         }
     }
 }
@@ -77,29 +99,223 @@ impl error::Error for Error {}
 /// Type alias for Result return type, used by this package.
 pub type Result<T> = result::Result<T, Error>;
 
+mod append;
+mod backend;
+#[cfg(feature = "serde")]
+mod codec;
+mod dedup;
+mod entry;
 mod evictor;
+mod expiry;
+#[cfg(feature = "serde")]
+mod export;
+mod ghost;
+mod handoff;
+mod heap;
+mod invalidate;
+mod keylocks;
 mod list;
 mod lru;
+mod pins;
+mod prefix;
+mod pressure;
+mod quota;
+#[cfg(all(feature = "shm", target_os = "linux"))]
+mod shm;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod stripes;
+mod tags;
+mod telemetry;
+mod waiters;
+
+pub use append::Append;
+#[cfg(feature = "dashmap")]
+pub use backend::DashMap;
+pub use backend::{Backend, DefaultHasher, DefaultMap, Fold, SeededState, Sharded};
+pub use backend::{Tiered, HOT_ENTRIES};
+#[cfg(feature = "bincode")]
+pub use codec::Bincode;
+#[cfg(feature = "cbor")]
+pub use codec::Cbor;
+#[cfg(feature = "serde")]
+pub use codec::Codec;
+#[cfg(feature = "encrypt")]
+pub use codec::Encrypted;
+#[cfg(feature = "msgpack")]
+pub use codec::MsgPack;
+pub use entry::EntryRef;
+pub use handoff::{EvictionReason, Handoff, HandoffFull};
+pub use heap::HeapSize;
+pub use keylocks::KeyGuard;
+pub use lru::{Admission, Conflict, Cursor, Health, Lru, LruBuilder, OlderThan, Policy};
+pub use lru::{PassStats, Priority, Reporter, Spawner, Stats, Touched, Update};
+pub use pins::PinGuard;
+pub use prefix::KeyBytes;
+#[cfg(target_os = "linux")]
+pub use pressure::CgroupPressure;
+pub use pressure::{memory_limit, Pressure};
+pub use quota::{GroupId, Quota, QuotaLimit};
+#[cfg(all(feature = "shm", target_os = "linux"))]
+pub use shm::ShmLru;
+#[cfg(feature = "serde")]
+pub use snapshot::{LoadReport, Schedule};
+#[cfg(feature = "log")]
+pub use telemetry::LogSink;
+pub use telemetry::{Event, TelemetrySink};
 
-pub use lru::{Lru, LruBuilder};
+#[cfg(feature = "derive")]
+pub use clru_derive::HeapSize;
 
-use std::sync::atomic::{AtomicPtr, Ordering::SeqCst};
+use std::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering::SeqCst,
+};
+use std::sync::Arc;
+use std::time::Duration;
 
 const MAX_ENTRIES: usize = 1_000_000; // maximum 1 million entries in cache.
+const MAX_FREQ: u64 = 255; // access counters saturate at this value.
+const CLOCK_REF: u8 = 1; // entry is referenced since the last sweep.
+const CLOCK_HOT: u8 = 2; // entry is hot, refer Policy::ClockPro.
+const RANK_BITS: u32 = 62; // victim ranks are prefixed with entry's priority.
 
 pub struct Value<K, V> {
-    value: V,
+    // key as stored in the cache, access nodes can be freed under a reader.
+    key: K,
+    // shared across keys when set via Lru::set_dedup.
+    value: Arc<V>,
     access: AtomicPtr<list::Node<K>>,
+    n_access: AtomicUsize,
+    // upper 32-bits is the aging epoch, lower 32-bits is the access count.
+    freq: AtomicU64,
+    // elapsed time in uS since UNIX_EPOCH, when the entry was last read or written.
+    last_access: AtomicU64,
+    // monotonically increasing across the cache, renewed on every set.
+    version: AtomicU64,
+    // elapsed time since UNIX_EPOCH, when the entry was inserted.
+    born: Duration,
+    // memory footprint of the entry, accounted against max_memory.
+    weight: usize,
+    // demoted by admission callback, evicted ahead of others.
+    demoted: bool,
+    // set by the evictor once the entry is past `soft_ttl`.
+    stale: AtomicBool,
+    // CLOCK_REF and CLOCK_HOT bits, used by Policy::ClockPro.
+    clock: AtomicU8,
+    // cost to recreate the value, refer Lru::set_with_cost, zero if unknown.
+    cost: u32,
+    // lower priority entries are evicted first, refer Lru::set_with_priority.
+    priority: Priority,
 }
 
-impl<K, V> Clone for Value<K, V>
-where
-    V: Clone,
-{
+impl<K: Clone, V> Clone for Value<K, V> {
     fn clone(&self) -> Self {
         Value {
-            value: self.value.clone(),
+            key: self.key.clone(),
+            value: Arc::clone(&self.value),
             access: AtomicPtr::new(self.access.load(SeqCst)),
+            n_access: AtomicUsize::new(self.n_access.load(SeqCst)),
+            freq: AtomicU64::new(self.freq.load(SeqCst)),
+            last_access: AtomicU64::new(self.last_access.load(SeqCst)),
+            version: AtomicU64::new(self.version.load(SeqCst)),
+            born: self.born,
+            weight: self.weight,
+            demoted: self.demoted,
+            stale: AtomicBool::new(self.stale.load(SeqCst)),
+            clock: AtomicU8::new(self.clock.load(SeqCst)),
+            cost: self.cost,
+            priority: self.priority,
         }
     }
 }
+
+impl<K, V> Value<K, V> {
+    /// Return the cached value, cloning it only when it is shared.
+    fn into_value(self) -> V
+    where
+        V: Clone,
+    {
+        Arc::try_unwrap(self.value).unwrap_or_else(|value| value.as_ref().clone())
+    }
+
+    /// Bump the saturating access counter, after aging it to `epoch`.
+    fn touch_freq(&self, epoch: u64) {
+        let _ = self.freq.fetch_update(SeqCst, SeqCst, |freq| {
+            let count = std::cmp::min(aged_freq(freq, epoch) + 1, MAX_FREQ);
+            Some((epoch << 32) | count)
+        });
+    }
+
+    /// Return the access count, aged to `epoch`.
+    fn to_freq(&self, epoch: u64) -> u64 {
+        aged_freq(self.freq.load(SeqCst), epoch)
+    }
+
+    /// Return the greedy-dual-size-frequency priority, aged to `epoch`. Aging
+    /// of access counts stands in for the inflation value of GDSF, and cost
+    /// defaults to 1 when unknown.
+    fn to_gdsf(&self, epoch: u64) -> f64 {
+        let cost = std::cmp::max(self.cost, 1) as f64;
+        (self.to_freq(epoch) as f64) * cost / (std::cmp::max(self.weight, 1) as f64)
+    }
+
+    /// Prefix victim's `rank` with entry's priority, so that victims are
+    /// picked from lower priorities first.
+    fn to_rank(&self, rank: u64) -> u64 {
+        ((self.priority as u64) << RANK_BITS) | (rank & ((1 << RANK_BITS) - 1))
+    }
+
+    /// Sweep the clock hand over this entry, return whether it is a victim.
+    /// Referenced cold entries turn hot, unreferenced hot entries turn cold
+    /// and reference bit is cleared, only unreferenced cold entries are
+    /// victims.
+    fn sweep(&self) -> bool {
+        let clock = self.clock.fetch_update(SeqCst, SeqCst, |clock| {
+            match (clock & CLOCK_HOT == 0, clock & CLOCK_REF == 0) {
+                (true, true) => None,
+                (true, false) | (false, false) => Some(CLOCK_HOT),
+                (false, true) => Some(0),
+            }
+        });
+        clock.is_err()
+    }
+
+    /// Record `now`, elapsed time since UNIX_EPOCH, as the last access time.
+    fn touch_access(&self, now: Duration) {
+        self.last_access.store(now.as_micros() as u64, SeqCst)
+    }
+
+    /// Return the access node for this entry.
+    fn as_node(&self) -> &list::Node<K> {
+        unsafe { self.access.load(SeqCst).as_ref().unwrap() }
+    }
+
+    /// Return the time elapsed since this entry was inserted.
+    fn to_age(&self, now: Duration) -> Duration {
+        now.checked_sub(self.born).unwrap_or_default()
+    }
+
+    /// Return the time elapsed since this entry was last read or written.
+    fn to_idle(&self, now: Duration) -> Duration {
+        now.checked_sub(self.to_last_access()).unwrap_or_default()
+    }
+
+    /// Return the last access time, as elapsed time since UNIX_EPOCH.
+    fn to_last_access(&self) -> Duration {
+        Duration::from_micros(self.last_access.load(SeqCst))
+    }
+}
+
+// access counts are halved for every elapsed epoch.
+fn aged_freq(freq: u64, epoch: u64) -> u64 {
+    let (then, count) = (freq >> 32, freq & 0xFFFF_FFFF);
+    match epoch.saturating_sub(then) {
+        n if n >= 64 => 0,
+        n => count >> n,
+    }
+}
+
+// `now` is elapsed time since UNIX_EPOCH, `decay` is the aging period.
+fn freq_epoch(now: Duration, decay: Duration) -> u64 {
+    now.as_secs() / std::cmp::max(decay.as_secs(), 1)
+}
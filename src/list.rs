@@ -1,18 +1,57 @@
-use log::debug;
-
-use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering::SeqCst};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::time::{self, Duration};
 
+use crate::telemetry::{Event, TelemetrySink};
 use crate::{Error, Result};
 
+/// Number of nodes, at the head of the list, left alone while unlinking.
+pub const HEAD_SKIP: usize = 5;
+
 // Use this as Arc<List>
+//
+// Nodes are unlinked and freed only by the evictor, and by `compact()`, while
+// holding the walk lock for writing. Other threads can walk the list while
+// holding the walk lock for reading, refer `walk()`.
 pub struct List<K> {
     head: AtomicPtr<Node<K>>,
+    walk: RwLock<()>,
+    pub prepends: Retries,
+    telemetry: Arc<dyn TelemetrySink>,
+    /// number of nodes allocated and not yet freed.
+    #[cfg(feature = "leak-detect")]
+    pub n_nodes: AtomicUsize,
 }
 
-impl<K> Default for List<K> {
-    fn default() -> List<K> {
-        List { head: AtomicPtr::new(Box::leak(Box::new(Node::Z))) }
+impl<K> List<K> {
+    pub fn new(telemetry: Arc<dyn TelemetrySink>) -> List<K> {
+        List {
+            head: AtomicPtr::new(Box::leak(Box::new(Node::Z))),
+            walk: RwLock::new(()),
+            prepends: Retries::default(),
+            telemetry,
+            #[cfg(feature = "leak-detect")]
+            n_nodes: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Retry metrics for a CAS loop.
+#[derive(Default)]
+pub struct Retries {
+    pub n_ops: AtomicUsize,
+    pub n_retries: AtomicUsize,
+    pub max_streak: AtomicUsize,
+}
+
+impl Retries {
+    /// Record an operation that succeeded after `retries` failed CAS.
+    pub fn record(&self, retries: usize) {
+        self.n_ops.fetch_add(1, SeqCst);
+        if retries > 0 {
+            self.n_retries.fetch_add(retries, SeqCst);
+            self.max_streak.fetch_max(retries, SeqCst);
+        }
     }
 }
 
@@ -22,32 +61,181 @@ impl<K> Drop for List<K> {
         // node and its entire chain shall be dropped.
         let now = time::Instant::now();
         std::mem::drop(node);
-        debug!("took {:?} to drop all the nodes", now.elapsed());
+        let elapsed = now.elapsed();
+        self.telemetry.event(Event::ListDropped { elapsed });
     }
 }
 
 impl<K> List<K> {
-    pub fn prepend(&self, mut key: K) -> Result<*mut Node<K>> {
+    pub fn prepend(&self, key: K) -> Result<*mut Node<K>> {
+        let born = err_at!(Fatal, time::UNIX_EPOCH.elapsed())?;
+        Ok(self.prepend_at(key, born))
+    }
+
+    /// Same as prepend, but stamp the node with `born` instead of now.
+    pub fn prepend_at(&self, mut key: K, born: Duration) -> *mut Node<K> {
+        let mut retries = 0;
         loop {
             let old_ptr = self.head.load(SeqCst);
             let next = unsafe { Box::from_raw(old_ptr) };
 
-            let node = Node::new_node(key, next)?;
+            let node = Node::new_node(key, born, next);
             let new_ptr = Box::leak(node);
 
             match self.head.compare_exchange(old_ptr, new_ptr, SeqCst, SeqCst) {
-                Ok(_) => break Ok(new_ptr),
+                Ok(_) => {
+                    #[cfg(feature = "leak-detect")]
+                    self.n_nodes.fetch_add(1, SeqCst);
+                    self.prepends.record(retries);
+                    break new_ptr;
+                }
                 Err(_) => {
                     let (k, next) = unsafe { Box::from_raw(new_ptr).unwrap() };
                     key = k;
                     Box::leak(next);
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    /// Lock the list against walkers, hold this while unlinking nodes.
+    pub fn lock_walk(&self) -> RwLockWriteGuard<'_, ()> {
+        self.walk.write().unwrap()
+    }
+
+    /// Keep nodes from being freed, hold this while dereferencing an entry's
+    /// access node.
+    pub fn read_walk(&self) -> RwLockReadGuard<'_, ()> {
+        self.walk.read().unwrap()
+    }
+
+    /// Walk the list from the most recent access node to the oldest, until
+    /// `callb` returns false.
+    pub fn walk<F>(&self, mut callb: F)
+    where
+        F: FnMut(&Node<K>) -> bool,
+    {
+        let _guard = self.walk.read().unwrap();
+
+        let mut node: &Node<K> = unsafe { self.head.load(SeqCst).as_ref().unwrap() };
+        while let Node::T { next, .. } = node {
+            if !callb(node) {
+                break;
+            }
+            node = next.as_ref().unwrap();
+        }
+    }
+
+    /// Same as walk, but from the oldest access node to the most recent.
+    pub fn walk_back<F>(&self, mut callb: F)
+    where
+        F: FnMut(&Node<K>) -> bool,
+    {
+        let _guard = self.walk.read().unwrap();
+
+        let mut nodes = vec![];
+        let mut node: &Node<K> = unsafe { self.head.load(SeqCst).as_ref().unwrap() };
+        while let Node::T { next, .. } = node {
+            nodes.push(node);
+            node = next.as_ref().unwrap();
+        }
+        for node in nodes.into_iter().rev() {
+            if !callb(node) {
+                break;
+            }
+        }
+    }
+
+    /// Free a chain of nodes, unlinked from the list.
+    pub fn free(&self, node: Option<Box<Node<K>>>) {
+        #[cfg(feature = "leak-detect")]
+        {
+            let (mut n, mut next) = (0, node.as_deref());
+            while let Some(Node::T { next: nn, .. }) = next {
+                n += 1;
+                next = nn.as_deref();
+            }
+            self.n_nodes.fetch_sub(n, SeqCst);
+        }
+        std::mem::drop(node)
+    }
+
+    /// Unlink and free deleted nodes, visiting at most `limit` nodes, return
+    /// the number of nodes freed. Like the evictor, this leaves the first few
+    /// nodes alone, to keep off concurrent prepends.
+    pub fn compact(&self, limit: usize) -> usize {
+        let _walk = self.lock_walk();
+
+        let mut slot = match self.as_mut_head() {
+            Some(Node::T { next, .. }) => next,
+            _ => return 0,
+        };
+        let mut n = 0;
+        for _ in 0..limit {
+            let deleted = match slot.as_deref() {
+                Some(Node::T { deleted, .. }) => deleted.load(SeqCst),
+                _ => break,
+            };
+            if deleted {
+                let mut node = slot.take().unwrap();
+                *slot = match node.as_mut() {
+                    Node::T { next, .. } => next.take(),
+                    Node::Z => unreachable!(),
+                };
+                self.free(Some(node));
+                n += 1;
+            } else {
+                slot = match slot.as_deref_mut() {
+                    Some(Node::T { next, .. }) => next,
+                    _ => unreachable!(),
+                };
+            }
+        }
+        n
+    }
+
+    /// Return the number of live nodes left alone at the head of the list, by
+    /// `as_mut_head`. Call this holding the walk lock.
+    pub fn n_head_live(&self) -> usize {
+        let mut n = 0;
+        let mut node: &Node<K> = unsafe { self.head.load(SeqCst).as_ref().unwrap() };
+        for _ in 0..=HEAD_SKIP {
+            node = match node {
+                Node::Z => break,
+                Node::T { deleted, next, .. } => {
+                    n += if deleted.load(SeqCst) { 0 } else { 1 };
+                    next.as_ref().unwrap()
+                }
+            }
+        }
+        n
+    }
+
+    /// Return keys of the live nodes left alone at the head of the list, by
+    /// `as_mut_head`. Call this holding the walk lock.
+    pub fn to_head_keys(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let mut keys = vec![];
+        let mut node: &Node<K> = unsafe { self.head.load(SeqCst).as_ref().unwrap() };
+        for _ in 0..=HEAD_SKIP {
+            node = match node {
+                Node::Z => break,
+                Node::T { key, deleted, next, .. } => {
+                    if !deleted.load(SeqCst) {
+                        keys.push(key.clone());
+                    }
+                    next.as_ref().unwrap()
                 }
             }
         }
+        keys
     }
 
     pub fn as_mut_head(&self) -> Option<&mut Node<K>> {
-        let mut skip = 5;
+        let mut skip = HEAD_SKIP;
         let mut node: &mut Node<K> = unsafe { self.head.load(SeqCst).as_mut().unwrap() };
 
         loop {
@@ -76,15 +264,14 @@ pub enum Node<K> {
 }
 
 impl<K> Node<K> {
-    fn new_node(key: K, next: Box<Node<K>>) -> Result<Box<Node<K>>> {
+    fn new_node(key: K, born: Duration, next: Box<Node<K>>) -> Box<Node<K>> {
         let node = Node::T {
             key,
             deleted: AtomicBool::new(false),
-            born: err_at!(Fatal, time::UNIX_EPOCH.elapsed())?,
+            born,
             next: Some(next),
         };
-
-        Ok(Box::new(node))
+        Box::new(node)
     }
 
     fn unwrap(self) -> (K, Box<Node<K>>) {
@@ -96,6 +283,27 @@ impl<K> Node<K> {
 }
 
 impl<K> Node<K> {
+    pub fn to_key(&self) -> &K {
+        match self {
+            Node::T { key, .. } => key,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn to_born(&self) -> Duration {
+        match self {
+            Node::T { born, .. } => *born,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        match self {
+            Node::T { deleted, .. } => deleted.load(SeqCst),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn delete(&self) {
         match self {
             Node::T { deleted, .. } => deleted.store(true, SeqCst),
@@ -1,80 +1,691 @@
-use log::{debug, error};
-
+use std::collections::{BTreeMap, HashMap};
 use std::hash::{BuildHasher, Hash};
-use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering::SeqCst};
-use std::{borrow::Borrow, sync::Arc, thread, time::Duration};
+#[cfg(feature = "serde")]
+use std::io;
+use std::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering::SeqCst,
+};
+use std::sync::{mpsc, Arc, Mutex};
+#[cfg(feature = "serde")]
+use std::time::Instant;
+use std::time::{Duration, UNIX_EPOCH};
+use std::{borrow::Borrow, convert::TryFrom, mem, thread};
+
+use crate::append::Append;
+use crate::backend::{Backend, DefaultHasher, DefaultMap, Fold, SeededState};
+#[cfg(feature = "serde")]
+use crate::codec::Codec;
+use crate::evictor::{self, Evictor};
+use crate::expiry::Expiry;
+#[cfg(feature = "serde")]
+use crate::export::{self, Record};
+use crate::handoff::{EvictionReason, Handoff, HandoffFull};
+use crate::invalidate::Invalidator;
+use crate::keylocks::{KeyGuard, KeyLocks};
+use crate::pins::{PinGuard, Pins};
+use crate::prefix::{self, KeyBytes, PrefixIndex};
+use crate::quota::{GroupId, Quota, QuotaLimit};
+#[cfg(feature = "serde")]
+use crate::snapshot::{self, LoadReport};
+use crate::stripes::Stripes;
+use crate::tags::TagIndex;
+use crate::telemetry::{self, Event, TelemetrySink};
+use crate::waiters::Waiters;
+use crate::{
+    dedup, ghost::Ghost, heap, list, EntryRef, Error, HeapSize, Pressure, Result, Value,
+};
+
+// version of an entry that is being replaced by set_if_version or upsert, or
+// removed by invalidate_tag.
+const LOCKED: u64 = u64::MAX;
+
+// max_threads for deterministic builds, fixing the number of shards.
+const DETERMINISTIC_THREADS: usize = 8;
+
+// background snapshots check for close, and for their interval, this often.
+#[cfg(feature = "serde")]
+const SNAPSHOT_POLL: Duration = Duration::from_millis(100);
+
+// resume_eviction checks for evictor's passes this often.
+const RESUME_POLL: Duration = Duration::from_millis(1);
+
+// number of keys yielded by iter_touch before their accesses are recorded.
+const TOUCH_BATCH: usize = 64;
+
+// estimated bookkeeping of the backing map per entry, a hash and a link.
+const MAP_OVERHEAD: usize = 2 * mem::size_of::<usize>();
 
-use crate::{evictor::Evictor, list, Result, Value};
+// return the memory footprint of an entry.
+type Weigher<K, V> = fn(&K, &V) -> usize;
 
-#[derive(Clone, Copy)]
-pub struct LruBuilder {
+// decide whether an entry shall be admitted into the cache.
+type Admit<K, V> = Arc<dyn Fn(&K, &V) -> Admission + Send + Sync>;
+
+// resolve a conflicting entry, refer [Conflict::Resolve].
+type Resolver<K, V> = Box<dyn FnMut(&K, &V, &V) -> V>;
+
+/// Spawner for the evictor, refer [LruBuilder::spawner].
+pub type Spawner = Arc<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+/// Callback receiving periodic statistics, refer [LruBuilder::stats_reporter].
+pub type Reporter = Arc<dyn Fn(Stats) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct LruBuilder<K, V> {
     /// maximum number of entries allowed to be cached, default is MAX_ENTRIES
     pub max_entries: usize,
     /// footprint of cache not to exceed configured `max_memory`, default is MAX_MEMORY
     pub max_memory: Option<usize>,
-    /// evict all entries older than `max_old`
+    /// reject sets of entries whose footprint, as accounted against
+    /// `max_memory`, exceeds `max_entry_weight`, with `Oversized` error.
+    /// Default is None.
+    pub max_entry_weight: Option<usize>,
+    /// when `max_memory` is None, derive it as this fraction of the container's
+    /// memory limit, or system RAM, refer [crate::memory_limit]. Shall be
+    /// within (0, 1], else build panics. Default is None.
+    pub memory_fraction: Option<f64>,
+    /// evict all entries whose access node is older than `max_old`. Access nodes
+    /// are renewed on recorded gets, refer `access_sample`, so this is not a
+    /// precise idle-time, use `time_to_idle` for that.
     pub max_old: Option<Duration>, // in seconds.
+    /// evict all entries that are not read or written for `time_to_idle`.
+    pub time_to_idle: Option<Duration>,
+    /// flag entries older than `soft_ttl` as stale, and fire the callback
+    /// registered via [Lru::on_stale], say to refresh them. Default is None.
+    pub soft_ttl: Option<Duration>,
+    /// remove stale entries only after they are older than `soft_ttl` plus
+    /// `stale_grace`, default is zero.
+    pub stale_grace: Duration,
+    /// entries inserted within `protect_young` are not evicted for capacity,
+    /// say so that freshly loaded entries survive until their first read.
+    /// They still expire, and can be removed explicitly. Default is None.
+    pub protect_young: Option<Duration>,
+    /// evictor paces its passes, from the rate at which entries are set, so
+    /// that the number of entries, and their footprint, stay within this
+    /// fraction above `max_entries` and `max_memory`. Default is 0.02.
+    pub overshoot_band: f64,
     /// maximum number of concurrent instances allowed on Lru, defaults to number of
     /// physical cores.
     pub max_threads: usize,
+    /// how [crate::Sharded] and [crate::Tiered] backends fold a key's hash to
+    /// pick its shard, default is [Fold::Mix]. cmap folds hashes its own way.
+    pub shard_fold: Fold,
+    /// record recency only once every `access_sample` gets on an entry, the phase
+    /// is derived from the key's hash. Default is 1, record every get.
+    pub access_sample: usize,
+    /// eviction policy to apply when cache exceeds `max_entries`, default is Lru.
+    pub policy: Policy,
+    /// overwriting an existing key renews its age, so that `soft_ttl` and
+    /// `max_old` count from the update. Default is true, refer [Update].
+    pub update_resets_ttl: bool,
+    /// overwriting an existing key moves it to the head of the access list.
+    /// Default is true, refer [Update].
+    pub update_promotes: bool,
+    /// access counters are halved once every `freq_decay`, default is 60 seconds.
+    pub freq_decay: Duration,
+    /// adapt `max_entries`, up to `adaptive_cap`, based on how often evicted keys
+    /// are set back into the cache. Default is None, `max_entries` is fixed.
+    pub adaptive_cap: Option<usize>,
+    /// watch for host memory pressure, refer [crate::CgroupPressure]. Default is
+    /// None.
+    pub pressure: Option<Arc<dyn Pressure>>,
+    /// while under memory pressure, evict entries down to `pressure_floor`.
+    /// Default is 0, and it must be set when `pressure` is, else build panics.
+    pub pressure_floor: usize,
+    /// as `(high, low)` fractions of `max_entries`, start evicting once the
+    /// number of entries crosses the high mark and evict down to the low mark
+    /// in the same pass, say `(1.0, 0.9)`. Default is None, evict down to
+    /// `max_entries` as soon as it is crossed.
+    pub watermarks: Option<(f64, f64)>,
+    /// name of the cache, evictor thread is named `clru-evictor-{name}`.
+    /// Default is None, evictor thread is named `clru-evictor`.
+    pub name: Option<String>,
+    /// stack size for the evictor thread, default is None, refer
+    /// [std::thread::Builder::stack_size].
+    pub stack_size: Option<usize>,
+    /// run the evictor via this spawner instead of a dedicated thread, refer
+    /// [LruBuilder::spawner]. Default is None.
+    pub spawner: Option<Spawner>,
+    /// run the evictor as a task on the current tokio runtime, instead of a
+    /// dedicated thread, its passes run on tokio's blocking pool. Build shall
+    /// be called from within the runtime. Closing the cache aborts the task,
+    /// a pass already under way runs to completion. Default is false.
+    #[cfg(feature = "async")]
+    pub tokio_task: bool,
+    /// report statistics once every interval, refer [LruBuilder::stats_reporter].
+    /// Default is None.
+    pub stats_reporter: Option<(Duration, Reporter)>,
+    /// with `leak-detect` feature, warn when the number of access nodes and
+    /// the number of entries plus tombstones differ by more than this.
+    /// Default is 1024.
+    #[cfg(feature = "leak-detect")]
+    pub leak_threshold: usize,
+    /// route lifecycle events, evictor errors and statistics at close, to this
+    /// sink. Default is [crate::LogSink] with the `log` feature, else events
+    /// are dropped.
+    pub telemetry: Arc<dyn TelemetrySink>,
+    /// per group limits, set via [LruBuilder::quota]. Default is None.
+    pub quota: Option<Arc<Quota<K>>>,
+    /// consumer of evicted entries, set via [LruBuilder::evict_to]. Default
+    /// is None, evicted entries are dropped.
+    pub handoff: Option<Arc<Handoff<K, V>>>,
+    /// index keys by their bytes, set via [LruBuilder::prefix_index]. Default
+    /// is None.
+    pub prefix_index: Option<KeyBytes<K>>,
+    /// periodic snapshots, set via [LruBuilder::snapshot_every]. Default is
+    /// None.
+    #[cfg(feature = "serde")]
+    pub snapshot: Option<Arc<snapshot::Schedule<K, V>>>,
+    /// number of periodic snapshots to keep, including the latest. Default
+    /// is 3.
+    #[cfg(feature = "serde")]
+    pub snapshot_keep: usize,
+}
+
+/// Admission decision returned by the callback installed via [Lru::admission].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Admission {
+    /// Admit the entry.
+    Admit,
+    /// Admit the entry, but evict it ahead of others under capacity pressure.
+    Demote,
+    /// Reject the entry, set shall fail with `Rejected` error.
+    Reject,
+}
+
+/// Priority of an entry, refer [Lru::set_with_priority]. Entries set via
+/// other APIs have `Normal` priority.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Best-effort entries, evicted ahead of all others.
+    Low = 0,
+    Normal = 1,
+    /// Must-keep entries, evicted only when no other entry is left to evict.
+    High = 2,
 }
 
-impl Default for LruBuilder {
-    fn default() -> LruBuilder {
+/// Whether overwriting an existing key renews its age and its recency, refer
+/// [Lru::set_with_update]. Setting a new key always starts afresh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Update {
+    /// renew entry's age, else retain the age of the value it replaces.
+    pub resets_ttl: bool,
+    /// move entry to the head of the access list, else retain the position
+    /// of the value it replaces.
+    pub promotes: bool,
+}
+
+impl Default for Update {
+    fn default() -> Update {
+        Update { resets_ttl: true, promotes: true }
+    }
+}
+
+/// Resolution of keys present in both caches, refer [Lru::absorb].
+pub enum Conflict<K, V> {
+    /// Keep the entry inserted last, this cache's entry on a tie.
+    KeepNewest,
+    /// Keep this cache's entry.
+    KeepSelf,
+    /// Set the value returned by the closure, called with the key, this
+    /// cache's value and the other cache's value, in that order.
+    Resolve(Resolver<K, V>),
+}
+
+/// Policy to pick victims when cache exceeds its limits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// Evict the least recently used entries.
+    Lru,
+    /// Evict entries with the lowest aged access frequency, among the least
+    /// recently used ones.
+    Lfu,
+    /// Approximate LIRS using CLOCK-Pro. Entries start cold and turn hot when
+    /// referenced again before the evictor's hand sweeps past them, or when
+    /// set back soon after being evicted. Only cold entries are evicted. Gets
+    /// only set a reference bit, they don't allocate an access node.
+    ClockPro,
+    /// Greedy-dual-size-frequency, evict entries with the lowest aged access
+    /// frequency per byte of footprint, preferring large and rarely used
+    /// entries. Applies to `max_memory` as well as `max_entries`, favoring
+    /// byte-hit-ratio over object-hit-ratio.
+    Gdsf,
+}
+
+impl<K, V> Default for LruBuilder<K, V> {
+    fn default() -> LruBuilder<K, V> {
         LruBuilder {
             max_entries: crate::MAX_ENTRIES,
             max_memory: None,
+            max_entry_weight: None,
+            memory_fraction: None,
             max_old: None,
+            time_to_idle: None,
+            soft_ttl: None,
+            stale_grace: Duration::default(),
+            protect_young: None,
+            overshoot_band: 0.02,
             max_threads: num_cpus::get_physical(),
+            shard_fold: Fold::default(),
+            access_sample: 1,
+            policy: Policy::Lru,
+            update_resets_ttl: true,
+            update_promotes: true,
+            freq_decay: Duration::from_secs(60),
+            adaptive_cap: None,
+            pressure: None,
+            pressure_floor: 0,
+            watermarks: None,
+            name: None,
+            stack_size: None,
+            spawner: None,
+            #[cfg(feature = "async")]
+            tokio_task: false,
+            stats_reporter: None,
+            #[cfg(feature = "leak-detect")]
+            leak_threshold: 1024,
+            telemetry: telemetry::default_sink(),
+            quota: None,
+            handoff: None,
+            prefix_index: None,
+            #[cfg(feature = "serde")]
+            snapshot: None,
+            #[cfg(feature = "serde")]
+            snapshot_keep: 3,
         }
     }
 }
 
-impl LruBuilder {
-    pub fn build<K, V, H>(self, hash_builder: H) -> Lru<K, V, H>
+impl<K, V> LruBuilder<K, V> {
+    /// Run the evictor via `spawner`, say on a managed thread pool, instead
+    /// of spawning a dedicated thread. The evictor loops until the cache is
+    /// closed, so `spawner` shall dedicate a thread to it. `name` and
+    /// `stack_size` are ignored, and [Lru::evictor_thread] returns None.
+    pub fn spawner<F>(mut self, spawner: F) -> Self
+    where
+        F: 'static + Send + Sync + Fn(Box<dyn FnOnce() + Send>),
+    {
+        self.spawner = Some(Arc::new(spawner));
+        self
+    }
+
+    /// Invoke `callb` with a snapshot of cache statistics once every
+    /// `interval`. Callback is invoked from the evictor, between its passes,
+    /// so it shall return quickly.
+    pub fn stats_reporter<F>(mut self, interval: Duration, callb: F) -> Self
+    where
+        F: 'static + Send + Sync + Fn(Stats),
+    {
+        self.stats_reporter = Some((interval, Arc::new(callb)));
+        self
+    }
+
+    /// Assign each key to a group, say a tenant, via `classifier`, and limit
+    /// the number of entries and the memory footprint of each group as per
+    /// `limits`. Groups without limits are bound only by the cache-wide ones.
+    /// Evictor removes the oldest entries of a group beyond its limits.
+    pub fn quota<F>(mut self, classifier: F, limits: HashMap<GroupId, QuotaLimit>) -> Self
+    where
+        F: 'static + Send + Sync + Fn(&K) -> GroupId,
+    {
+        let quota: Quota<K> = Quota::new(Arc::new(classifier), limits);
+        self.quota = Some(Arc::new(quota));
+        self
+    }
+
+    /// Send entries removed by the evictor, for limits, expiry or invalidation,
+    /// to `tx` along with the reason, instead of dropping them, say to spill
+    /// them to disk or into a colder cache. When the channel is full, entries
+    /// are dropped or the evictor blocks, as per `full`. Entries are also
+    /// dropped once the receiver is gone. Entries removed explicitly, or
+    /// replaced, are not sent.
+    pub fn evict_to(
+        mut self,
+        tx: mpsc::SyncSender<(K, V, EvictionReason)>,
+        full: HandoffFull,
+    ) -> Self
+    where
+        K: 'static + Send,
+        V: 'static + Send + Clone,
+    {
+        self.handoff = Some(Arc::new(Handoff::new(tx, full)));
+        self
+    }
+
+    /// Index keys by their bytes, so that [Lru::invalidate_prefix] looks up
+    /// matching entries and removes them right away, instead of invalidating
+    /// them lazily. Index holds a copy of every key set, keys of evicted
+    /// entries are pruned as the index grows.
+    pub fn prefix_index(mut self) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        self.prefix_index = Some(prefix::key_bytes::<K>);
+        self
+    }
+
+    /// Write a snapshot of the cache to `path`, encoded via `codec`, once every
+    /// `interval`, from a background thread that exits when the cache is
+    /// closed. Snapshots are written to a temporary file and renamed over
+    /// `path`, the older ones are kept as `path.1`, `path.2` and so on, up to
+    /// `snapshot_keep`. Load them via [Lru::load]. Outcome of each snapshot is
+    /// raised as telemetry event.
+    #[cfg(feature = "serde")]
+    pub fn snapshot_every<C>(
+        mut self,
+        interval: Duration,
+        path: std::path::PathBuf,
+        codec: C,
+    ) -> Self
+    where
+        K: 'static + serde::Serialize,
+        V: 'static + serde::Serialize,
+        C: 'static + Send + Sync + Codec,
+    {
+        let encode: snapshot::Encoder<K, V> =
+            Arc::new(move |record| codec.encode(record));
+        let schedule = snapshot::Schedule { interval, path, encode };
+        self.snapshot = Some(Arc::new(schedule));
+        self
+    }
+
+    /// Build a cache, memory footprint of each entry is accounted as the inline
+    /// size of its key and value.
+    pub fn build<H>(self, hash_builder: H) -> Lru<K, V, H>
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        H: 'static + Send + Clone + BuildHasher,
+        DefaultMap<K, Value<K, V>, H>: 'static + Backend<K, Value<K, V>, H>,
+    {
+        self.build_weighed(hash_builder, heap::shallow_weight)
+    }
+
+    /// Same as build, using [DefaultHasher], selected by the `ahash` feature.
+    pub fn build_default(self) -> Lru<K, V>
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        DefaultMap<K, Value<K, V>, DefaultHasher>:
+            'static + Backend<K, Value<K, V>, DefaultHasher>,
+    {
+        self.build(DefaultHasher::default())
+    }
+
+    /// Same as build, using [SeededState] hasher with `seed`, and pinning
+    /// `max_threads` to a fixed value, so that the same keys land on the same
+    /// shards on every run and on every host. Meant for tests and simulations.
+    pub fn deterministic(mut self, seed: u64) -> Lru<K, V, SeededState>
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        DefaultMap<K, Value<K, V>, SeededState>:
+            'static + Backend<K, Value<K, V>, SeededState>,
+    {
+        self.max_threads = DETERMINISTIC_THREADS;
+        self.build(SeededState::new(seed))
+    }
+
+    /// Build a cache, memory footprint of each entry is accounted using
+    /// [HeapSize], including heap memory owned by its key and value.
+    pub fn build_heap_sized<H>(self, hash_builder: H) -> Lru<K, V, H>
+    where
+        K: 'static + Send + Clone + Eq + Hash + HeapSize,
+        V: 'static + Send + Clone + HeapSize,
+        H: 'static + Send + Clone + BuildHasher,
+        DefaultMap<K, Value<K, V>, H>: 'static + Backend<K, Value<K, V>, H>,
+    {
+        self.build_weighed(hash_builder, heap::heap_weight)
+    }
+
+    /// Same as build, but entries are held in backing map `M`, refer [Backend].
+    pub fn build_backend<H, M>(self, hash_builder: H) -> Lru<K, V, H, M>
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        H: 'static + Send + Clone + BuildHasher,
+        M: 'static + Backend<K, Value<K, V>, H>,
+    {
+        self.build_weighed(hash_builder, heap::shallow_weight)
+    }
+
+    fn build_weighed<H, M>(
+        self,
+        hash_builder: H,
+        weigher: Weigher<K, V>,
+    ) -> Lru<K, V, H, M>
     where
-        K: 'static + Send + Clone + PartialEq + Hash,
+        K: 'static + Send + Clone + Eq + Hash,
         V: 'static + Send + Clone,
         H: 'static + Send + Clone + BuildHasher,
+        M: 'static + Backend<K, Value<K, V>, H>,
     {
-        let map = cmap::Map::new(self.max_threads + 1, hash_builder);
-        let access_list = Arc::new(list::List::default());
+        if self.pressure.is_some() && self.pressure_floor == 0 {
+            panic!("pressure_floor of 0 clears the cache under memory pressure");
+        }
+        match self.memory_fraction {
+            Some(fraction) if !(fraction > 0.0 && fraction <= 1.0) => {
+                panic!("memory_fraction {} is not within (0, 1]", fraction)
+            }
+            _ => (),
+        }
+
+        let max_memory = match (self.max_memory, self.memory_fraction) {
+            (None, Some(fraction)) => {
+                let limit = crate::memory_limit();
+                limit.map(|limit| ((limit as f64) * fraction) as usize)
+            }
+            (max_memory, _) => max_memory,
+        };
+
+        // one handle more for the evictor, and one for swaps.
+        let mut map = M::new(self.max_threads + 2, hash_builder.clone());
+        map.set_fold(self.shard_fold);
+        let swapper = Arc::new(Mutex::new(map.cloned()));
+        let access_list = Arc::new(list::List::new(Arc::clone(&self.telemetry)));
         let cur_entries = Arc::new(AtomicUsize::new(0));
         let cur_memory = Arc::new(AtomicUsize::new(0));
         let closed = Arc::new(AtomicBool::new(false));
+        let frozen = Arc::new(AtomicBool::new(false));
+        let max_entries = Arc::new(AtomicUsize::new(self.max_entries));
+        let counters = Arc::new(evictor::Counters::default());
+        counters.alive.store(true, SeqCst);
+        let pins = Arc::new(Pins::default());
+        let quota = self.quota.clone();
+        let handoff = self.handoff.clone();
+        let ghost = match (self.adaptive_cap, self.policy) {
+            (Some(cap), Policy::ClockPro) => {
+                let n = cap.saturating_sub(self.max_entries);
+                Some(Arc::new(Ghost::new(std::cmp::max(n, self.max_entries))))
+            }
+            (Some(cap), _) => {
+                let n = cap.saturating_sub(self.max_entries);
+                Some(Arc::new(Ghost::new(n)))
+            }
+            // entries start cold, evictor sizes this to resident cold entries.
+            (None, Policy::ClockPro) => Some(Arc::new(Ghost::new(self.max_entries))),
+            (None, _) => None,
+        };
 
         let evictor = Evictor {
-            max_entries: self.max_entries,
-            max_memory: self.max_memory,
+            max_entries: Arc::clone(&max_entries),
+            base_entries: self.max_entries,
+            adaptive_cap: self.adaptive_cap,
+            pressure: self.pressure.clone(),
+            pressure_floor: self.pressure_floor,
+            watermarks: self.watermarks,
+            reporter: self.stats_reporter.clone(),
+            telemetry: Arc::clone(&self.telemetry),
+            #[cfg(feature = "leak-detect")]
+            leak_threshold: self.leak_threshold,
+            max_memory,
             max_old: self.max_old,
+            time_to_idle: self.time_to_idle,
+            soft_ttl: self.soft_ttl,
+            stale_grace: self.stale_grace,
+            protect_young: self.protect_young,
+            overshoot_band: self.overshoot_band.max(0.0),
+            policy: self.policy,
+            freq_decay: self.freq_decay,
 
             list: Arc::clone(&access_list),
             cur_entries: Arc::clone(&cur_entries),
             cur_memory: Arc::clone(&cur_memory),
             closed: Arc::clone(&closed),
-
-            n_evicted: 0,
-            n_deleted: 0,
-            n_older: 0,
+            frozen: Arc::clone(&frozen),
+            counters: Arc::clone(&counters),
+            ghost: ghost.clone(),
+            pins: Arc::clone(&pins),
+            quota,
+        };
+        let invalidator = Arc::new(Invalidator::default());
+        let expiry = Arc::new(Expiry::default());
+        #[cfg(feature = "async")]
+        let (evictor, task) = match self.tokio_task {
+            true => {
+                let (map, hash_builder) = (map.cloned(), hash_builder.clone());
+                let (invalidator, expiry) =
+                    (Arc::clone(&invalidator), Arc::clone(&expiry));
+                let (handoff, telemetry) = (handoff.clone(), Arc::clone(&self.telemetry));
+                let task = tokio::spawn(async move {
+                    let res = evictor.run_async(
+                        map,
+                        hash_builder,
+                        invalidator,
+                        expiry,
+                        handoff,
+                    );
+                    if let Err(err) = res.await {
+                        telemetry.event(Event::EvictorFail(&err))
+                    }
+                });
+                (None, Some(task))
+            }
+            false => (Some(evictor), None),
         };
-        let handle = {
-            let map = map.cloned();
-            thread::spawn(move || evictor.run(map))
+        #[cfg(not(feature = "async"))]
+        let evictor = Some(evictor);
+
+        let (handle, done) = match evictor {
+            Some(evictor) => {
+                let (tx, rx) = mpsc::sync_channel(1);
+                let job = {
+                    let (map, hash_builder) = (map.cloned(), hash_builder.clone());
+                    let (invalidator, expiry) =
+                        (Arc::clone(&invalidator), Arc::clone(&expiry));
+                    let handoff = handoff.clone();
+                    move || {
+                        let res =
+                            evictor.run(map, hash_builder, invalidator, expiry, handoff);
+                        tx.send(res).ok();
+                    }
+                };
+                let handle = match self.spawner.as_ref() {
+                    Some(spawner) => {
+                        spawner(Box::new(job));
+                        None
+                    }
+                    None => {
+                        let name = match self.name.as_ref() {
+                            Some(name) => format!("clru-evictor-{}", name),
+                            None => "clru-evictor".to_string(),
+                        };
+                        let builder = match self.stack_size {
+                            Some(stack_size) => {
+                                thread::Builder::new().name(name).stack_size(stack_size)
+                            }
+                            None => thread::Builder::new().name(name),
+                        };
+                        Some(builder.spawn(job).unwrap())
+                    }
+                };
+                (handle, Some(rx))
+            }
+            None => (None, None),
         };
 
+        #[cfg(feature = "serde")]
+        if let Some(schedule) = self.snapshot.clone() {
+            let (map, list) = (map.cloned(), Arc::clone(&access_list));
+            let (invalidator, closed) = (Arc::clone(&invalidator), Arc::clone(&closed));
+            let (telemetry, keep) = (Arc::clone(&self.telemetry), self.snapshot_keep);
+            let name = match self.name.as_ref() {
+                Some(name) => format!("clru-snapshot-{}", name),
+                None => "clru-snapshot".to_string(),
+            };
+            let job = move || {
+                let mut last = Instant::now();
+                while !closed.load(SeqCst) {
+                    thread::sleep(std::cmp::min(SNAPSHOT_POLL, schedule.interval));
+                    if last.elapsed() < schedule.interval || closed.load(SeqCst) {
+                        continue;
+                    }
+                    let start = Instant::now();
+                    let res = schedule.write(keep, |callb| {
+                        export::walk_records(&list, &map, &invalidator, callb)
+                    });
+                    match res {
+                        Ok(n_entries) => {
+                            let elapsed = start.elapsed();
+                            telemetry.event(Event::Snapshot { n_entries, elapsed })
+                        }
+                        Err(err) => telemetry.event(Event::SnapshotFail(&err)),
+                    }
+                    last = Instant::now();
+                }
+            };
+            thread::Builder::new().name(name).spawn(job).unwrap();
+        }
+
         let inner = Inner {
-            evictor: Some(handle),
-            n_gets: AtomicUsize::new(0),
-            n_sets: AtomicUsize::new(0),
+            evictor: handle,
+            #[cfg(feature = "async")]
+            task,
+            done: Mutex::new(done),
+            n_versions: AtomicU64::new(0),
+            max_entries,
+            counters,
+            ghost,
+            list: Arc::clone(&access_list),
             closed,
+            frozen,
+            telemetry: Arc::clone(&self.telemetry),
+            config: self.clone(),
         };
 
         Lru {
             max_entries: self.max_entries,
-            max_memory: self.max_memory,
+            max_memory,
+            max_entry_weight: self.max_entry_weight,
             max_old: self.max_old,
+            time_to_idle: self.time_to_idle,
+            soft_ttl: self.soft_ttl,
+            stale_grace: self.stale_grace,
+            max_threads: self.max_threads,
+            access_sample: std::cmp::max(self.access_sample, 1) as u64,
+            freq_decay: self.freq_decay,
+            policy: self.policy,
+            update: Update {
+                resets_ttl: self.update_resets_ttl,
+                promotes: self.update_promotes,
+            },
 
+            weigher,
+            admit: None,
+            invalidator,
+            tags: Arc::new(TagIndex::default()),
+            prefixes: self.prefix_index.map(|to_bytes| {
+                Arc::new(PrefixIndex::new(self.max_threads * 4, to_bytes))
+            }),
+            expiry,
+            pins,
+            key_locks: Arc::new(KeyLocks::default()),
+            waiters: Arc::new(Waiters::default()),
+            hash_builder,
             map,
+            swapper,
+            values: Arc::new(dedup::Table::default()),
+            stripes: Arc::new(Stripes::new(self.max_threads * 4)),
             inner: Arc::new(inner),
             list: access_list,
             cur_entries,
@@ -83,54 +694,139 @@ impl LruBuilder {
     }
 }
 
-pub struct Lru<K, V, H = cmap::DefaultHasher> {
+pub struct Lru<K, V, H = DefaultHasher, M = DefaultMap<K, Value<K, V>, H>> {
     max_entries: usize,
     max_memory: Option<usize>,
+    max_entry_weight: Option<usize>,
     max_old: Option<Duration>,
+    time_to_idle: Option<Duration>,
+    soft_ttl: Option<Duration>,
+    stale_grace: Duration,
+    max_threads: usize,
+    access_sample: u64,
+    freq_decay: Duration,
+    policy: Policy,
+    update: Update,
 
-    map: cmap::Map<K, Value<K, V>, H>,
-    inner: Arc<Inner<K>>,
+    weigher: Weigher<K, V>,
+    admit: Option<Admit<K, V>>,
+    invalidator: Arc<Invalidator<K, V>>,
+    tags: Arc<TagIndex<K>>,
+    prefixes: Option<Arc<PrefixIndex<K>>>,
+    expiry: Arc<Expiry<K, V>>,
+    pins: Arc<Pins<K>>,
+    key_locks: Arc<KeyLocks<K>>,
+    waiters: Arc<Waiters>,
+    hash_builder: H,
+    map: M,
+    // map handle shared by swaps across all handles, refer Lru::swap.
+    swapper: Arc<Mutex<M>>,
+    values: Arc<dedup::Table<V>>,
+    stripes: Arc<Stripes>,
+    inner: Arc<Inner<K, V>>,
     list: Arc<list::List<K>>,
     cur_entries: Arc<AtomicUsize>,
     cur_memory: Arc<AtomicUsize>,
 }
 
-struct Inner<K> {
-    evictor: Option<thread::JoinHandle<Result<Evictor<K>>>>,
-    n_gets: AtomicUsize,
-    n_sets: AtomicUsize,
+struct Inner<K, V> {
+    evictor: Option<thread::JoinHandle<()>>,
+    #[cfg(feature = "async")]
+    task: Option<tokio::task::JoinHandle<()>>,
+    done: Mutex<Option<mpsc::Receiver<Result<Evictor<K>>>>>,
+    n_versions: AtomicU64,
+    max_entries: Arc<AtomicUsize>,
+    counters: Arc<evictor::Counters>,
+    ghost: Option<Arc<Ghost>>,
+    list: Arc<list::List<K>>,
     closed: Arc<AtomicBool>,
+    frozen: Arc<AtomicBool>,
+    telemetry: Arc<dyn TelemetrySink>,
+    // configuration the cache was built with, refer Lru::split_off.
+    config: LruBuilder<K, V>,
 }
 
-impl<K> Drop for Inner<K> {
-    fn drop(&mut self) {
+impl<K, V> Inner<K, V> {
+    fn to_stats(&self) -> Stats {
+        to_stats_with(&self.counters, &self.list, &self.max_entries, |c| c.load(SeqCst))
+    }
+
+    // same as to_stats, but also zero the counters.
+    fn reset_stats(&self) -> Stats {
+        to_stats_with(&self.counters, &self.list, &self.max_entries, |c| {
+            c.swap(0, SeqCst)
+        })
+    }
+}
+
+impl<K, V> Inner<K, V> {
+    // stop the evictor and wait for it to exit.
+    fn close(&mut self) -> Result<()> {
         self.closed.store(true, SeqCst);
+        // close may be called from within the runtime, abort the task instead
+        // of blocking on it.
+        #[cfg(feature = "async")]
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
 
-        match self.evictor.take().unwrap().join() {
-            Ok(Ok(evictor)) => {
-                let stats = Stats {
-                    n_gets: self.n_gets.load(SeqCst),
-                    n_sets: self.n_sets.load(SeqCst),
-                    n_evicted: evictor.n_evicted,
-                    n_deleted: evictor.n_deleted,
-                    n_older: evictor.n_older,
-                };
-                debug!("{:?}", stats);
-            }
-            Ok(Err(err)) => error!("evictor fail: {}", err),
-            Err(err) => error!("evictor thread fail {:?}", err),
+        let done = self.done.lock().unwrap().take();
+        let res = match done.map(|done| done.recv()) {
+            Some(Ok(Ok(_))) | None => Ok(()),
+            Some(Ok(Err(err))) => Err(err),
+            Some(Err(_)) => err_at!(Fatal, msg: "evictor exited without result"),
+        };
+
+        match self.evictor.take().map(|handle| handle.join()) {
+            Some(Ok(())) | None => res,
+            Some(Err(err)) => err_at!(Fatal, msg: "evictor thread fail {:?}", err),
+        }
+    }
+}
+
+impl<K, V> Drop for Inner<K, V> {
+    fn drop(&mut self) {
+        match self.close() {
+            Ok(()) => self.telemetry.event(Event::Closed(&self.to_stats())),
+            Err(err) => self.telemetry.event(Event::EvictorFail(&err)),
         }
     }
 }
 
-impl<K, V, H> Clone for Lru<K, V, H> {
+impl<K, V, H, M> Clone for Lru<K, V, H, M>
+where
+    H: Clone,
+    M: Backend<K, Value<K, V>, H>,
+{
     fn clone(&self) -> Self {
         Lru {
             max_entries: self.max_entries,
             max_memory: self.max_memory,
+            max_entry_weight: self.max_entry_weight,
             max_old: self.max_old,
+            time_to_idle: self.time_to_idle,
+            soft_ttl: self.soft_ttl,
+            stale_grace: self.stale_grace,
+            max_threads: self.max_threads,
+            access_sample: self.access_sample,
+            freq_decay: self.freq_decay,
+            policy: self.policy,
+            update: self.update,
 
+            weigher: self.weigher,
+            admit: self.admit.clone(),
+            invalidator: Arc::clone(&self.invalidator),
+            tags: Arc::clone(&self.tags),
+            prefixes: self.prefixes.clone(),
+            expiry: Arc::clone(&self.expiry),
+            pins: Arc::clone(&self.pins),
+            key_locks: Arc::clone(&self.key_locks),
+            waiters: Arc::clone(&self.waiters),
+            hash_builder: self.hash_builder.clone(),
             map: self.map.cloned(),
+            swapper: Arc::clone(&self.swapper),
+            values: Arc::clone(&self.values),
+            stripes: Arc::clone(&self.stripes),
             inner: Arc::clone(&self.inner),
             list: Arc::clone(&self.list),
             cur_entries: Arc::clone(&self.cur_entries),
@@ -139,63 +835,2170 @@ impl<K, V, H> Clone for Lru<K, V, H> {
     }
 }
 
-impl<K, V, H> Lru<K, V, H> {
-    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+impl<K, V, H, M> Lru<K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+{
+    /// Install an admission callback, invoked on every set before the entry is
+    /// inserted, to reject or demote entries before they displace others.
+    /// Install this before cloning the handle, clones share the callback.
+    pub fn admission<F>(mut self, callb: F) -> Self
     where
-        K: Borrow<Q>,
-        Q: ToOwned<Owned = K> + PartialEq + Hash,
+        F: 'static + Send + Sync + Fn(&K, &V) -> Admission,
+    {
+        self.admit = Some(Arc::new(callb));
+        self
+    }
+
+    /// Invalidate all entries, inserted so far, for which `predicate` returns
+    /// true. Invalidation is deferred, matching entries are treated as missing
+    /// by get and removed by the evictor during its subsequent passes.
+    pub fn invalidate_entries_if<F>(&self, predicate: F)
+    where
+        F: 'static + Send + Sync + Fn(&K, &V) -> bool,
+    {
+        let version = self.inner.n_versions.load(SeqCst);
+        self.invalidator.register(version, Arc::new(predicate));
+    }
+
+    /// Remove all entries in constant time, entries inserted before this call
+    /// are treated as absent by gets and are reclaimed lazily by the evictor,
+    /// until then they count against `len` and limits. Same as
+    /// invalidate_entries_if with an always-true predicate, without the cost
+    /// of applying it.
+    pub fn clear(&self) {
+        let version = self.inner.n_versions.load(SeqCst);
+        self.invalidator.clear(version);
+    }
+
+    /// Switch the cache, across all its handles, to read-only. Sets, updates,
+    /// loads and removals like take, remove_if and invalidate_tag fail with
+    /// `Frozen` error, leaving the entries as is. Evictor stops removing
+    /// entries, for limits or for expiry, and only reclaims access nodes of
+    /// entries already removed or replaced, so the cache can exceed its
+    /// limits. Gets still record their access. Entries invalidated in the
+    /// meantime read as absent, and are reclaimed once unfrozen. Writes in
+    /// flight when frozen may still complete.
+    pub fn freeze(&self) {
+        self.inner.frozen.store(true, SeqCst);
+    }
+
+    /// Undo [Lru::freeze], limits are enforced from evictor's next pass.
+    pub fn unfreeze(&self) {
+        self.inner.frozen.store(false, SeqCst);
+    }
+
+    /// Return whether the cache is frozen, refer [Lru::freeze].
+    pub fn is_frozen(&self) -> bool {
+        self.inner.frozen.load(SeqCst)
+    }
+
+    /// Stop enforcing `max_entries`, `max_memory` and quotas, across all
+    /// handles of the cache, so that a bulk load can run past the limits
+    /// without the evictor removing what is being loaded. Entries still
+    /// expire. Time spent paused is reported as [Stats::time_paused].
+    pub fn pause_eviction(&self) {
+        let now = UNIX_EPOCH.elapsed().unwrap_or_default().as_micros() as u64;
+        let paused_since = &self.inner.counters.paused_since;
+        paused_since.compare_exchange(0, std::cmp::max(now, 1), SeqCst, SeqCst).ok();
+    }
+
+    /// Undo [Lru::pause_eviction], and wait for the evictor to complete a pass
+    /// started after this call, bringing the cache back within its limits.
+    /// Fail if the evictor is not running.
+    pub fn resume_eviction(&self) -> Result<()> {
+        let counters = &self.inner.counters;
+        let since = match counters.paused_since.swap(0, SeqCst) {
+            0 => return Ok(()),
+            since => since,
+        };
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?.as_micros() as u64;
+        let elapsed = now.saturating_sub(since) as usize;
+        counters.time_paused.fetch_add(elapsed, SeqCst);
+
+        // a pass in progress may have started while paused, wait for the next.
+        let mut n_passes = 0;
+        let mut last_pass = counters.last_pass.load(SeqCst);
+        while n_passes < 2 {
+            if !counters.alive.load(SeqCst) {
+                err_at!(Fatal, msg: "evictor is not running")?;
+            }
+            thread::sleep(RESUME_POLL);
+            let pass = counters.last_pass.load(SeqCst);
+            if pass != last_pass {
+                n_passes += 1;
+                last_pass = pass;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Invalidate all entries, inserted so far, whose key starts with `prefix`.
+    /// Useful for hierarchical keys like `tenant/collection/id`. With
+    /// [LruBuilder::prefix_index], matching entries are looked up in the
+    /// index and removed right away, return the number of entries removed.
+    /// Else, and while the cache is frozen, this applies like
+    /// [Lru::invalidate_entries_if] and returns 0.
+    pub fn invalidate_prefix<P>(&mut self, prefix: P) -> usize
+    where
+        K: Clone + Eq + Hash + AsRef<[u8]>,
         H: BuildHasher,
-        V: Clone,
+        P: AsRef<[u8]>,
     {
-        let val = self.map.get_with(key, |value: &Value<K, V>| loop {
-            let optr = value.access.load(SeqCst);
-            let nptr = self.list.prepend(key.to_owned())?;
-            match value.access.compare_exchange(optr, nptr, SeqCst, SeqCst) {
-                Ok(_) => {
-                    unsafe { optr.as_ref().unwrap() }.delete();
-                    break Ok(value.value.clone());
-                }
-                Err(_) => {
-                    unsafe { nptr.as_ref().unwrap() }.delete();
+        let prefix = prefix.as_ref().to_vec();
+        let prefixes = match self.prefixes.clone() {
+            Some(prefixes) if !self.is_frozen() => prefixes,
+            _ => {
+                self.invalidate_entries_if(move |key, _| {
+                    key.as_ref().starts_with(&prefix)
+                });
+                return 0;
+            }
+        };
+
+        // entries set after this call are left alone, and stay indexed.
+        let version = self.inner.n_versions.load(SeqCst);
+        let stripes = Arc::clone(&self.stripes);
+        let mut n = 0;
+        for key in prefixes.to_keys(&prefix).into_iter() {
+            let hash = self.key_hash(&key);
+            let _guard = stripes.lock(hash);
+
+            let invalidator = &self.invalidator;
+            let res = self.map.get_with(&key, |value: &Value<K, V>| {
+                let ver = value.version.load(SeqCst);
+                (ver != LOCKED && ver <= version, invalidator.is_invalid(value))
+            });
+            match res {
+                Some((true, false)) => {
+                    n += self.remove_entry(&key).map(|_| 1).unwrap_or(0);
+                    prefixes.remove(hash, &key);
                 }
+                Some((true, true)) | None => prefixes.remove(hash, &key),
+                Some((false, _)) => (),
             }
+        }
+        n
+    }
 
-            self.inner.n_gets.fetch_add(1, SeqCst);
-        });
+    /// Register `callb` to be fired, once, when `key` expires due to `max_old`,
+    /// `time_to_idle` or `soft_ttl` plus `stale_grace`. Callback is fired from
+    /// the evictor thread, with the expired value, and is not fired when `key`
+    /// is evicted for capacity or removed explicitly, it stays registered
+    /// until `key` expires or the callback is cancelled via
+    /// [Lru::cancel_on_expire].
+    pub fn on_expire<F>(&self, key: K, callb: F)
+    where
+        K: Eq + Hash,
+        H: BuildHasher,
+        F: 'static + Send + FnOnce(&K, &V),
+    {
+        let hash = self.key_hash(&key);
+        self.expiry.register(hash, key, Box::new(callb))
+    }
+
+    /// Cancel callbacks registered for `key` via [Lru::on_expire], return the
+    /// number of callbacks cancelled.
+    pub fn cancel_on_expire(&self, key: &K) -> usize
+    where
+        K: Eq + Hash,
+        H: BuildHasher,
+    {
+        self.expiry.cancel(self.key_hash(key), key)
+    }
 
-        val.transpose()
+    /// Register `callb` to be fired, once per entry, when the entry gets older
+    /// than `soft_ttl`, say to refresh it ahead of its removal after the
+    /// `stale_grace`. Callback is fired from the evictor thread, with the stale
+    /// value, it may set the key afresh. Replaces the earlier callback, if any,
+    /// across all handles.
+    pub fn on_stale<F>(&self, callb: F)
+    where
+        K: Eq,
+        F: 'static + Send + Sync + Fn(&K, &V),
+    {
+        self.expiry.set_on_stale(Some(Arc::new(callb)))
     }
 
-    pub fn set(&mut self, key: K, value: V) -> Result<Option<V>>
+    /// Pin `key`, protecting its entry from eviction, for capacity, memory or
+    /// expiry, until it is unpinned. Pins are counted, and apply to the key
+    /// whether or not it is in the cache, including values set afterwards.
+    pub fn pin(&self, key: K)
     where
-        K: Clone + PartialEq + Hash,
-        V: Clone,
+        K: Eq + Hash,
         H: BuildHasher,
     {
-        self.inner.n_sets.fetch_add(1, SeqCst);
+        self.pins.pin(self.key_hash(&key), key)
+    }
 
-        let value = Value {
-            value,
-            access: AtomicPtr::new(self.list.prepend(key.clone())?),
+    /// Drop a pin on `key`, return false if `key` was not pinned.
+    pub fn unpin(&self, key: &K) -> bool
+    where
+        K: Eq + Hash,
+        H: BuildHasher,
+    {
+        self.pins.unpin(self.key_hash(key), key)
+    }
+
+    /// Pin `key` for as long as the returned guard is alive, refer [Lru::pin].
+    pub fn pin_guard(&self, key: K) -> PinGuard<K>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        PinGuard::new(Arc::clone(&self.pins), self.key_hash(&key), key)
+    }
+
+    /// Lock `key` for as long as the returned guard is alive, blocking until
+    /// the lock is released by its current holder, if any, across all handles
+    /// of the cache. Key need not be cached, say to compute and validate its
+    /// value before setting it, one caller at a time. Locks are advisory, gets
+    /// and sets on `key` are not blocked. Locking a key held by the same
+    /// thread blocks forever.
+    pub fn lock_key(&self, key: K) -> KeyGuard<K>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let hash = self.key_hash(&key);
+        self.key_locks.lock(hash, key.clone());
+        KeyGuard::new(Arc::clone(&self.key_locks), hash, key)
+    }
+
+    /// Same as lock_key, but return None instead of blocking if `key` is
+    /// already locked.
+    pub fn try_lock_key(&self, key: K) -> Option<KeyGuard<K>>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let hash = self.key_hash(&key);
+        match self.key_locks.try_lock(hash, key.clone()) {
+            true => Some(KeyGuard::new(Arc::clone(&self.key_locks), hash, key)),
+            false => None,
+        }
+    }
+
+    /// Return a snapshot of cache statistics.
+    pub fn stats(&self) -> Stats {
+        self.inner.to_stats()
+    }
+
+    /// Return the health of the cache, refer [Health]. This walks the entire
+    /// access list to count tombstones.
+    pub fn health(&self) -> Health {
+        let counters = &self.inner.counters;
+        let last_pass = match counters.last_pass.load(SeqCst) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
         };
+        let now = UNIX_EPOCH.elapsed().unwrap_or_default();
+
+        let mut n_tombstones = 0;
+        self.list.walk(|node| {
+            n_tombstones += if node.is_deleted() { 1 } else { 0 };
+            true
+        });
+
+        let memory = self.cur_memory.load(SeqCst);
+        Health {
+            evictor_alive: counters.alive.load(SeqCst),
+            since_last_pass: last_pass.map(|t| now.checked_sub(t).unwrap_or_default()),
+            entries_overshoot: self.len().saturating_sub(self.max_entries()),
+            memory_overshoot: self.max_memory.map_or(0, |m| memory.saturating_sub(m)),
+            n_tombstones,
+        }
+    }
+
+    /// Zero the cache statistics, return the snapshot taken just before. Does
+    /// not affect the current `max_entries`.
+    pub fn reset_stats(&self) -> Stats {
+        self.inner.reset_stats()
+    }
 
-        match self.map.set(key, value) {
-            Some(Value { value, access }) => {
-                unsafe { access.load(SeqCst).as_ref().unwrap() }.delete();
-                Ok(Some(value))
+    /// Return the evictor thread, to observe it, say for per-thread CPU
+    /// accounting.
+    pub fn evictor_thread(&self) -> Option<&thread::Thread> {
+        self.inner.evictor.as_ref().map(|handle| handle.thread())
+    }
+
+    /// Close this handle. If this is the last handle on the cache, stop the
+    /// evictor thread, wait for it to exit and return the final statistics.
+    /// Else return None, evictor is stopped when the last handle is dropped.
+    pub fn close(self) -> Result<Option<Stats>> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(mut inner) => {
+                inner.close()?;
+                Ok(Some(inner.to_stats()))
             }
-            None => Ok(None),
+            Err(_) => Ok(None),
         }
     }
-}
 
-#[derive(Debug)]
-pub struct Stats {
-    pub n_gets: usize,
-    pub n_sets: usize,
-    pub n_evicted: usize,
-    pub n_deleted: usize,
-    pub n_older: usize,
+    /// Return the current count-limit, which differs from the configured
+    /// `max_entries` when adapted, refer `adaptive_cap`.
+    pub fn max_entries(&self) -> usize {
+        self.inner.max_entries.load(SeqCst)
+    }
+
+    /// Return the size-limit, derived from `memory_fraction` if configured so.
+    pub fn max_memory(&self) -> Option<usize> {
+        self.max_memory
+    }
+
+    /// Return the configured `max_old`.
+    pub fn max_old(&self) -> Option<Duration> {
+        self.max_old
+    }
+
+    /// Return the configured `time_to_idle`.
+    pub fn time_to_idle(&self) -> Option<Duration> {
+        self.time_to_idle
+    }
+
+    /// Return the configured `max_threads`, the backing map is sized for
+    /// `max_threads + 1` concurrent handles, including the evictor.
+    pub fn max_threads(&self) -> usize {
+        self.max_threads
+    }
+
+    /// Return the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.cur_entries.load(SeqCst)
+    }
+
+    /// Return whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Free access nodes left behind by updates and accesses, without waiting
+    /// for the evictor, visiting at most `limit` nodes from the most recent
+    /// one. Return the number of nodes freed. Blocks the evictor, and other
+    /// walkers, for the duration of the call.
+    pub fn compact(&self, limit: usize) -> usize {
+        let n = self.list.compact(limit);
+        self.inner.counters.n_deleted.fetch_add(n, SeqCst);
+        n
+    }
+
+    /// Return the estimated memory held by the cache, that is, the footprint
+    /// of entries, as accounted for `max_memory`, along with the entry's
+    /// metadata, access node and an estimate of the backing map's overhead,
+    /// plus deleted access nodes yet to be reclaimed by the evictor. Like
+    /// [Lru::health], this walks the entire access list.
+    pub fn estimated_memory(&self) -> usize {
+        let mut n_tombstones = 0;
+        self.list.walk(|node| {
+            n_tombstones += if node.is_deleted() { 1 } else { 0 };
+            true
+        });
+
+        let node = mem::size_of::<list::Node<K>>();
+        let overhead = mem::size_of::<Value<K, V>>() + node + MAP_OVERHEAD;
+        self.cur_memory.load(SeqCst) + (self.len() * overhead) + (n_tombstones * node)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        V: Clone,
+    {
+        self.read(key, |value| value.value.as_ref().clone())
+    }
+
+    /// Same as get, additionally return the key as stored in the cache, which
+    /// can differ in form from the lookup `key`, say when keys are
+    /// canonicalized before set while lookups use raw input.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Result<Option<(K, V)>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        V: Clone,
+    {
+        self.read(key, |value| (value.key.clone(), value.value.as_ref().clone()))
+    }
+
+    /// Same as get, but apply `callb` on the cached value and return its
+    /// result, instead of cloning the value.
+    pub fn get_with<Q, F, R>(&self, key: &Q, callb: F) -> Result<Option<R>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        F: FnOnce(&V) -> R,
+    {
+        let mut callb = Some(callb);
+        self.read(key, |value| (callb.take().unwrap())(value.value.as_ref()))
+    }
+
+    /// Same as get, additionally return the entry's version, which can be
+    /// supplied to [Lru::set_if_version].
+    pub fn get_versioned<Q>(&self, key: &Q) -> Result<Option<(V, u64)>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        V: Clone,
+    {
+        self.read(key, |value| {
+            let version = value.version.load(SeqCst);
+            (value.value.as_ref().clone(), version)
+        })
+    }
+
+    /// Same as get, but return the entry's value along with its metadata, as
+    /// observed by the same lookup.
+    pub fn get_entry<Q>(&self, key: &Q) -> Result<Option<EntryRef<'_, K, V, H, M>>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        V: Clone,
+    {
+        // walk lock keeps the access node from being freed under us.
+        let _walk = self.list.read_walk();
+        let entry = self.read(key, |value| {
+            let node_born = value.as_node().to_born();
+            let expires = [
+                self.max_old.map(|max_old| node_born + max_old),
+                self.time_to_idle.map(|tti| value.to_last_access() + tti),
+                self.soft_ttl.map(|ttl| value.born + ttl + self.stale_grace),
+            ];
+            EntryRef {
+                lru: self,
+                key: value.key.clone(),
+                value: value.value.as_ref().clone(),
+                born: value.born,
+                expires: expires.iter().flatten().min().cloned(),
+                stale: self.soft_ttl.map(|ttl| value.born + ttl),
+            }
+        })?;
+
+        Ok(entry)
+    }
+
+    /// Same as get, but if `key` is missing, wait for it to be set from any of
+    /// the cache handles, for up to `timeout`. Return None if `key` is still
+    /// missing by then.
+    pub fn wait_for<Q>(&self, key: &Q, timeout: Duration) -> Result<Option<V>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        V: Clone,
+    {
+        let waiters = Arc::clone(&self.waiters);
+        let res = waiters.wait(timeout, || self.get(key).transpose());
+        res.transpose()
+    }
+
+    /// Same as wait_for, but wait without blocking the thread. Shall be
+    /// awaited from within a tokio runtime with timers enabled.
+    #[cfg(feature = "async")]
+    pub async fn wait_for_async<Q>(&self, key: &Q, timeout: Duration) -> Result<Option<V>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        V: Clone,
+    {
+        let waiters = Arc::clone(&self.waiters);
+        let res = waiters.wait_async(timeout, || self.get(key).transpose()).await;
+        res.transpose()
+    }
+
+    /// Return the least recently used entry, which is the evictor's next
+    /// victim under `Policy::Lru`, as `(key, value, age)`, without recording
+    /// an access or removing it. This walks the entire access list, and looks
+    /// up entries from its tail until a valid one is found.
+    pub fn peek_lru(&self) -> Result<Option<(K, V, Duration)>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+
+        let mut item = None;
+        self.list.walk_back(|node| {
+            if !node.is_deleted() {
+                item = self.map.get_with(node.to_key(), |value: &Value<K, V>| match self
+                    .invalidator
+                    .is_invalid(value)
+                {
+                    true => None,
+                    false => {
+                        let age = now.checked_sub(value.born).unwrap_or_default();
+                        Some((value.key.clone(), value.value.as_ref().clone(), age))
+                    }
+                });
+            }
+            item.as_ref().is_none_or(Option::is_none)
+        });
+
+        Ok(item.flatten())
+    }
+
+    /// Iterate over entries that were inserted more than `age` ago, starting
+    /// from the least recently used one. Entries are looked up as the iterator
+    /// is consumed, without recording an access.
+    pub fn iter_older_than(&self, age: Duration) -> Result<OlderThan<'_, K, V, H, M>>
+    where
+        K: Clone,
+    {
+        let mut keys = vec![];
+        self.list.walk(|node| {
+            if !node.is_deleted() {
+                keys.push(node.to_key().clone())
+            }
+            true
+        });
+
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        Ok(OlderThan { lru: self, keys, age, now })
+    }
+
+    /// Return up to `count` entries, along with the cursor to resume from, to
+    /// walk the cache in bounded chunks. Start with `Cursor::default()`, the
+    /// scan is complete when the returned cursor [Cursor::is_done]. Every
+    /// entry present for the whole scan is returned at least once, entries
+    /// set or removed in the meantime may or may not be. Entries are picked
+    /// in the order of their key's hash, which is stable across the scan,
+    /// unlike their recency, so each chunk walks the entire access list.
+    /// Entries are read without recording an access.
+    pub fn scan(&self, cursor: Cursor, count: usize) -> (Vec<(K, V)>, Cursor)
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        if cursor.done || count == 0 {
+            return (vec![], cursor);
+        }
+
+        // keys with the smallest hashes at or after the cursor, by hash.
+        let (mut keys, mut full) = (BTreeMap::<u64, Vec<K>>::new(), false);
+        self.list.walk(|node| {
+            if node.is_deleted() {
+                return true;
+            }
+            let (key, hash) = (node.to_key(), self.key_hash(node.to_key()));
+            if hash < cursor.from {
+                return true;
+            }
+            let same = keys.entry(hash).or_default();
+            if !same.contains(key) {
+                same.push(key.clone());
+            }
+            if keys.len() > count {
+                keys.pop_last();
+                full = true;
+            }
+            true
+        });
+
+        let next = match keys.keys().next_back() {
+            Some(hash) if full && *hash < u64::MAX => {
+                Cursor { from: hash + 1, done: false }
+            }
+            _ => Cursor { from: u64::MAX, done: true },
+        };
+        let entries = keys
+            .into_values()
+            .flatten()
+            .filter_map(|key| {
+                let value = self.map.get_with(&key, |value: &Value<K, V>| {
+                    match self.invalidator.is_invalid(value) {
+                        true => None,
+                        false => Some(value.value.as_ref().clone()),
+                    }
+                });
+                value.flatten().map(|value| (key, value))
+            })
+            .collect();
+
+        (entries, next)
+    }
+
+    /// Same as `iter_older_than` with zero age, but record an access for each
+    /// yielded entry, as get does, say for cache-warming jobs that want every
+    /// entry they validated marked as recently used. Accesses are recorded in
+    /// batches as the iterator is consumed, and for the rest when it is
+    /// dropped. `iter_older_than` remains recency-neutral.
+    pub fn iter_touch(&self) -> Result<Touched<'_, K, V, H, M>>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let iter = self.iter_older_than(Duration::default())?;
+        Ok(Touched { iter, batch: Vec::with_capacity(TOUCH_BATCH) })
+    }
+
+    /// Write every entry to `writer` as JSON-lines, one object per entry with
+    /// its `key`, `value`, `age_ms` since insertion and `hits`, least recently
+    /// used first. Meant for debugging, entries are read without recording an
+    /// access. Return the number of entries written.
+    #[cfg(feature = "serde")]
+    pub fn export_jsonl<W>(&self, mut writer: W) -> Result<usize>
+    where
+        K: Clone + Eq + Hash + serde::Serialize,
+        V: Clone + serde::Serialize,
+        H: BuildHasher,
+        W: io::Write,
+    {
+        let n = self.walk_records(|record| {
+            err_at!(FailConvert, serde_json::to_writer(&mut writer, record))?;
+            err_at!(IOError, writer.write_all(b"\n"))
+        })?;
+        err_at!(IOError, writer.flush())?;
+
+        Ok(n)
+    }
+
+    /// Set entries read from `reader`, as written by [Lru::export_jsonl],
+    /// retaining their age and hit count. Entries rejected by the admission
+    /// callback, or for exceeding `max_entry_weight`, are skipped. Return the
+    /// number of entries set.
+    #[cfg(feature = "serde")]
+    pub fn import_jsonl<R>(&mut self, reader: R) -> Result<usize>
+    where
+        K: Clone + Eq + Hash + serde::de::DeserializeOwned,
+        V: Clone + serde::de::DeserializeOwned,
+        H: BuildHasher,
+        R: io::BufRead,
+    {
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        let mut n = 0;
+        for line in reader.lines() {
+            let line = err_at!(IOError, line)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record<K, V> = err_at!(FailConvert, serde_json::from_str(&line))?;
+            if self.set_record(now, record)? {
+                n += 1;
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Write a snapshot of every entry to `writer`, encoded via `codec`, least
+    /// recently used first. Records carry the same fields as
+    /// [Lru::export_jsonl], and are checksummed individually and as a whole,
+    /// refer [Lru::load]. Entries are read without recording an access.
+    /// Return the number of entries written.
+    #[cfg(feature = "serde")]
+    pub fn save<W, C>(&self, writer: W, codec: &C) -> Result<usize>
+    where
+        K: Clone + Eq + Hash + serde::Serialize,
+        V: Clone + serde::Serialize,
+        H: BuildHasher,
+        W: io::Write,
+        C: Codec,
+    {
+        let mut writer = snapshot::Writer::new(writer)?;
+        let n = self.walk_records(|record| writer.append(&codec.encode(record)?))?;
+        writer.finish()?;
+
+        Ok(n)
+    }
+
+    /// Set entries read from a snapshot written by [Lru::save], decoded via
+    /// the same `codec`, retaining their age and hit count. Fail if `reader`
+    /// is not a snapshot, or is of another format version. Records failing
+    /// their checksum, or their decode, are skipped, and a truncated snapshot,
+    /// say from a torn write, is loaded up to its last whole record. Both are
+    /// reported in the returned [LoadReport], along with entries rejected by
+    /// the admission callback, or for exceeding `max_entry_weight`.
+    #[cfg(feature = "serde")]
+    pub fn load<R, C>(&mut self, reader: R, codec: &C) -> Result<LoadReport>
+    where
+        K: Clone + Eq + Hash + serde::de::DeserializeOwned,
+        V: Clone + serde::de::DeserializeOwned,
+        H: BuildHasher,
+        R: io::Read,
+        C: Codec,
+    {
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        let mut reader = snapshot::Reader::new(reader)?;
+        let mut report = LoadReport::default();
+        loop {
+            let data = match reader.next_frame()? {
+                snapshot::Frame::Record(data) => data,
+                snapshot::Frame::Corrupt => {
+                    report.n_corrupt += 1;
+                    continue;
+                }
+                snapshot::Frame::End(complete) => {
+                    report.complete = complete;
+                    break;
+                }
+            };
+            let record = match codec.decode(&data) {
+                Ok(record) => record,
+                Err(_) => {
+                    report.n_corrupt += 1;
+                    continue;
+                }
+            };
+            match self.set_record(now, record)? {
+                true => report.n_loaded += 1,
+                false => report.n_rejected += 1,
+            }
+        }
+
+        Ok(report)
+    }
+
+    pub fn set(&mut self, key: K, value: V) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        let demoted = self.admit(&key, &value)?;
+        let value = self.new_value(key.clone(), Arc::new(value), demoted)?;
+        Ok(self.insert(key, value).map(Value::into_value))
+    }
+
+    /// Same as set, with a hint on how expensive `value` is to recreate.
+    /// Under `Policy::Lru` the evictor prefers low-cost entries among victims
+    /// of similar recency, under `Policy::Lfu` among victims of the same
+    /// frequency, and `Policy::Gdsf` weighs frequency by cost.
+    pub fn set_with_cost(&mut self, key: K, value: V, cost: u32) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+        self.inner.counters.n_costed.fetch_add(1, SeqCst);
+
+        let demoted = self.admit(&key, &value)?;
+        let mut value = self.new_value(key.clone(), Arc::new(value), demoted)?;
+        value.cost = cost;
+        Ok(self.insert(key, value).map(Value::into_value))
+    }
+
+    /// Same as set, with `priority`. When the cache exceeds `max_entries`, the
+    /// evictor picks victims from lower priorities before touching higher
+    /// ones.
+    pub fn set_with_priority(
+        &mut self,
+        key: K,
+        value: V,
+        priority: Priority,
+    ) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+        if priority != Priority::Normal {
+            self.inner.counters.n_prioritized.fetch_add(1, SeqCst);
+        }
+
+        let demoted = self.admit(&key, &value)?;
+        let mut value = self.new_value(key.clone(), Arc::new(value), demoted)?;
+        value.priority = priority;
+        Ok(self.insert(key, value).map(Value::into_value))
+    }
+
+    /// Same as set, but when `key` is already cached, renew its age and its
+    /// recency only as per `update`, instead of as configured by
+    /// `update_resets_ttl` and `update_promotes`.
+    pub fn set_with_update(
+        &mut self,
+        key: K,
+        value: V,
+        update: Update,
+    ) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        let demoted = self.admit(&key, &value)?;
+        let value = self.new_value(key.clone(), Arc::new(value), demoted)?;
+        Ok(self.insert_with(key, value, update).map(Value::into_value))
+    }
+
+    /// Same as set, but through a shared reference, return the value replaced,
+    /// if any. Writes on the same key are serialized like set, but swaps from
+    /// all handles share a single map handle and are applied one at a time,
+    /// prefer set for write heavy paths.
+    pub fn swap(&self, key: K, value: V) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        let demoted = self.admit(&key, &value)?;
+        let mut value = self.new_value(key.clone(), Arc::new(value), demoted)?;
+
+        let _guard = self.stripes.lock(self.key_hash(&key));
+        let (nptr, kept_key) = self.before_insert(&key, &mut value, self.update);
+        let indexed = self.prefixes.as_ref().map(|_| key.clone());
+        let old = self.swapper.lock().unwrap().set(key, value);
+        if let Some(key) = indexed.as_ref() {
+            self.add_prefix(key);
+        }
+        Ok(self.after_insert(old, nptr, kept_key).map(Value::into_value))
+    }
+
+    /// Get values for all `keys`, loading the missing ones with a single call
+    /// to `loader`, which shall return the loaded `(key, value)` pairs in any
+    /// order. Loaded entries are inserted into the cache. Return values in the
+    /// same order as `keys`, None for keys that are neither cached nor loaded.
+    /// While the cache is frozen, loaded values are returned without inserting
+    /// them.
+    pub fn get_many_or_load<F>(&mut self, keys: &[K], loader: F) -> Result<Vec<Option<V>>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+        F: FnOnce(&[K]) -> Result<Vec<(K, V)>>,
+    {
+        let mut values = Vec::with_capacity(keys.len());
+        let mut misses = vec![];
+        for key in keys.iter() {
+            let value = self.get(key)?;
+            if value.is_none() {
+                misses.push(key.clone());
+            }
+            values.push(value);
+        }
+
+        if misses.is_empty() {
+            return Ok(values);
+        }
+
+        for (key, value) in loader(&misses)?.into_iter() {
+            for (i, _) in keys.iter().enumerate().filter(|(_, k)| *k == &key) {
+                if values[i].is_none() {
+                    values[i] = Some(value.clone());
+                }
+            }
+            match self.set(key, value) {
+                Ok(_) | Err(Error::Rejected(_, _)) | Err(Error::Oversized(_, _)) => (),
+                Err(Error::Frozen(_, _)) => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Same as set, but identical values are stored once and shared across all
+    /// the keys referring to them. Shared values are released when the last
+    /// entry referring to them is evicted or overwritten.
+    pub fn set_dedup(&mut self, key: K, value: V) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        let demoted = self.admit(&key, &value)?;
+        let value = self.values.intern(self.key_hash(&value), value);
+        let value = self.new_value(key.clone(), value, demoted)?;
+        Ok(self.insert(key, value).map(Value::into_value))
+    }
+
+    /// Same as set, additionally tag the entry with each of `tags`, so that
+    /// it can be removed along with other entries carrying the same tag via
+    /// [Lru::invalidate_tag].
+    pub fn set_tagged(&mut self, key: K, value: V, tags: &[&str]) -> Result<Option<V>>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        let demoted = self.admit(&key, &value)?;
+        let value = self.new_value(key.clone(), Arc::new(value), demoted)?;
+        let version = value.version.load(SeqCst);
+        let old = self.insert(key.clone(), value);
+
+        let is_live = |key: &K, version: u64| {
+            let res = self.map.get_with(key, |v: &Value<K, V>| v.version.load(SeqCst));
+            res == Some(version)
+        };
+        for tag in tags.iter() {
+            self.tags.add(tag, key.clone(), version, is_live);
+        }
+
+        Ok(old.map(Value::into_value))
+    }
+
+    /// Remove all entries carrying `tag`, return the number of entries removed.
+    /// Entries overwritten after they were tagged are not removed. Fail with
+    /// `Frozen` while the cache is frozen, refer [Lru::freeze].
+    pub fn invalidate_tag(&mut self, tag: &str) -> Result<usize>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        self.check_frozen()?;
+
+        let mut n = 0;
+        for (key, version) in self.tags.take(tag).into_iter() {
+            // claim the entry, so that it is not removed after an overwrite.
+            let claimed = self.map.get_with(&key, |value: &Value<K, V>| {
+                let res = value.version.compare_exchange(version, LOCKED, SeqCst, SeqCst);
+                res.is_ok()
+            });
+            if let Some(true) = claimed {
+                n += self.remove_entry(&key).map(|_| 1).unwrap_or(0);
+            }
+        }
+        Ok(n)
+    }
+
+    /// Set `value` for `key` only if the entry's current version matches
+    /// `version`, as returned by [Lru::get_versioned]. Fail with
+    /// `VersionMismatch` if the entry was updated, or removed, since then.
+    pub fn set_if_version(&mut self, key: K, value: V, version: u64) -> Result<V>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+    {
+        let demoted = self.admit(&key, &value)?;
+
+        // hold the stripe lock, like set, so that no write slips in between
+        // the claim and the insert.
+        let stripes = Arc::clone(&self.stripes);
+        let _guard = stripes.lock(self.key_hash(&key));
+
+        // claim the entry, concurrent set_if_version calls on the same version
+        // shall fail from here on.
+        let claimed = self.map.get_with(&key, |value: &Value<K, V>| {
+            let res = value.version.compare_exchange(version, LOCKED, SeqCst, SeqCst);
+            res.is_ok()
+        });
+        match claimed {
+            Some(true) => (),
+            Some(false) => err_at!(VersionMismatch, msg: "version {}", version)?,
+            None => err_at!(VersionMismatch, msg: "missing entry")?,
+        }
+
+        let value = match self.new_value(key.clone(), Arc::new(value), demoted) {
+            Ok(value) => value,
+            Err(err) => {
+                self.unclaim(&key, version);
+                return Err(err);
+            }
+        };
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        match self.insert_locked(key, value, self.update) {
+            Some(old) => Ok(old.into_value()),
+            None => err_at!(VersionMismatch, msg: "entry removed concurrently"),
+        }
+    }
+
+    /// Set `key` to the value computed by `callb` from its current value, or
+    /// from None if `key` is missing. Upserts on the same key, from any of the
+    /// cache handles, are applied one after the other, and so are sets and
+    /// removals racing with them, which are not lost.
+    pub fn upsert<F>(&mut self, key: K, callb: F) -> Result<()>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+        F: FnOnce(Option<&V>) -> V,
+    {
+        self.update(key, |old| Ok(callb(old)))
+    }
+
+    /// Set `value` for `key` if missing, else set it to `combine(old, value)`.
+    /// Applied like [Lru::upsert], one after the other for the same key.
+    pub fn merge<F>(&mut self, key: K, value: V, combine: F) -> Result<()>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+        F: Fn(V, V) -> V,
+    {
+        self.update(key, |old| match old {
+            Some(old) => Ok(combine(old.clone(), value)),
+            None => Ok(value),
+        })
+    }
+
+    /// Add `delta` to the integer value of `key`, treating a missing entry as
+    /// zero, and return the new value. Fail with `Overflow` if the new value
+    /// does not fit in `V`, leaving the entry as is. Applied like
+    /// [Lru::upsert], one after the other for the same key.
+    pub fn increment(&mut self, key: K, delta: i64) -> Result<i64>
+    where
+        K: Clone + Eq + Hash,
+        V: Copy + Into<i64> + TryFrom<i64>,
+        H: BuildHasher,
+    {
+        let mut res = 0;
+        self.update(key, |old| {
+            let old: i64 = old.map(|old| (*old).into()).unwrap_or(0);
+            res = match old.checked_add(delta) {
+                Some(res) => res,
+                None => err_at!(Overflow, msg: "{} + {}", old, delta)?,
+            };
+            match V::try_from(res) {
+                Ok(value) => Ok(value),
+                Err(_) => err_at!(Overflow, msg: "{} + {}", old, delta),
+            }
+        })?;
+        Ok(res)
+    }
+
+    /// Same as increment, but subtract `delta`.
+    pub fn decrement(&mut self, key: K, delta: i64) -> Result<i64>
+    where
+        K: Clone + Eq + Hash,
+        V: Copy + Into<i64> + TryFrom<i64>,
+        H: BuildHasher,
+    {
+        match delta.checked_neg() {
+            Some(delta) => self.increment(key, delta),
+            None => err_at!(Overflow, msg: "-{}", delta),
+        }
+    }
+
+    /// Remove `key` only if `predicate` holds over its current value, return
+    /// the removed value. Check and removal are atomic with respect to other
+    /// writes on `key` with [crate::Sharded], [crate::Tiered] and dashmap
+    /// backends, refer [Backend::remove_if]. Entries being updated by
+    /// set_if_version, upsert or invalidate_tag are not removed. Fail with
+    /// `Frozen` while the cache is frozen.
+    pub fn remove_if<Q, F>(&mut self, key: &Q, predicate: F) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        V: Clone,
+        F: Fn(&V) -> bool,
+    {
+        self.check_frozen()?;
+
+        let invalidator = Arc::clone(&self.invalidator);
+        let value = self.map.remove_if(key, |value: &Value<K, V>| {
+            value.version.load(SeqCst) != LOCKED
+                && !invalidator.is_invalid(value)
+                && predicate(value.value.as_ref())
+        });
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        value.as_node().delete();
+        self.cur_entries.fetch_sub(1, SeqCst);
+        self.cur_memory.fetch_sub(value.weight, SeqCst);
+        Ok(Some(value.into_value()))
+    }
+
+    /// Remove `key` and return its value, handing it over to the caller. Value
+    /// is moved out of the cache, it is copied only if shared, say by a
+    /// set_dedup. Unlike eviction, no callbacks are fired and the key is not
+    /// remembered as a victim, callbacks registered via [Lru::on_expire] stay
+    /// registered, refer [Lru::cancel_on_expire]. Fail with `Frozen` while
+    /// the cache is frozen.
+    pub fn take<Q>(&mut self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: Eq + Hash + ?Sized,
+        V: Clone,
+        H: BuildHasher,
+    {
+        self.check_frozen()?;
+
+        let stripes = Arc::clone(&self.stripes);
+        let _guard = stripes.lock(self.key_hash(key));
+
+        Ok(self.remove_entry(key).map(Value::into_value))
+    }
+
+    /// Drain entries from `other` into this cache, least recently used first,
+    /// so that their relative recency is roughly retained, ahead of this
+    /// cache's own entries. Absorbed entries retain their age, hit count,
+    /// cost and priority, and are subject to this cache's admission
+    /// callback, rejected entries are dropped. Keys present in both caches are
+    /// resolved as per `conflict`, holding the key's stripe lock, values set
+    /// via [Conflict::Resolve] start afresh. Tags, pins and on_expire
+    /// callbacks of `other` are not carried over. Return the number of
+    /// entries absorbed.
+    pub fn absorb(
+        &mut self,
+        mut other: Self,
+        mut conflict: Conflict<K, V>,
+    ) -> Result<usize>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        self.check_frozen()?;
+
+        let mut keys = vec![];
+        other.list.walk(|node| {
+            if !node.is_deleted() {
+                keys.push(node.to_key().clone())
+            }
+            true
+        });
+
+        // least recently used first, walk yields the most recent first.
+        let mut n = 0;
+        while let Some(key) = keys.pop() {
+            let value = match other.remove_entry(&key) {
+                Some(value) if !other.invalidator.is_invalid(&value) => value,
+                _ => continue,
+            };
+            if self.absorb_entry(key, value, &mut conflict)? {
+                n += 1;
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Move entries matching `predicate` into a new cache, built with the same
+    /// configuration and admission callback as this one, say to hand over a
+    /// tenant's entries. Moved entries retain their age, hit count, cost and
+    /// priority, and their relative recency. Periodic snapshots are not
+    /// configured on the new cache, and tags, pins and on_expire callbacks of
+    /// moved entries are not carried over. Entries being updated by
+    /// set_if_version, upsert or invalidate_tag are not moved.
+    pub fn split_off<F>(&mut self, predicate: F) -> Result<Self>
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        H: 'static + Send + Clone + BuildHasher,
+        M: 'static,
+        F: Fn(&K, &V) -> bool,
+    {
+        self.check_frozen()?;
+        let mut other = self.rebuild();
+
+        let mut keys = vec![];
+        self.list.walk(|node| {
+            if !node.is_deleted() {
+                keys.push(node.to_key().clone())
+            }
+            true
+        });
+
+        // least recently used first, walk yields the most recent first.
+        let invalidator = Arc::clone(&self.invalidator);
+        while let Some(key) = keys.pop() {
+            let value = self.map.remove_if(&key, |value: &Value<K, V>| {
+                value.version.load(SeqCst) != LOCKED
+                    && !invalidator.is_invalid(value)
+                    && predicate(&key, value.value.as_ref())
+            });
+            if let Some(value) = value {
+                value.as_node().delete();
+                self.cur_entries.fetch_sub(1, SeqCst);
+                self.cur_memory.fetch_sub(value.weight, SeqCst);
+                other.absorb_entry(key, value, &mut Conflict::KeepSelf)?;
+            }
+        }
+
+        Ok(other)
+    }
+
+    /// Build an independent cache, with the same configuration and admission
+    /// callback as this one, and its own map, access list and evictor, holding
+    /// a copy of every entry. Copies retain their age, hit count, cost and
+    /// priority, and their relative recency, entries set or removed in the
+    /// meantime may or may not be copied. Unlike clone, which returns another
+    /// handle to the same cache, later writes on either cache are not seen by
+    /// the other. Periodic snapshots are not configured on the copy, and tags,
+    /// pins and on_expire callbacks are not carried over.
+    pub fn deep_clone(&self) -> Result<Self>
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        H: 'static + Send + Clone + BuildHasher,
+        M: 'static,
+    {
+        let mut other = self.rebuild();
+
+        let mut keys = vec![];
+        self.list.walk(|node| {
+            if !node.is_deleted() {
+                keys.push(node.to_key().clone())
+            }
+            true
+        });
+
+        // least recently used first, walk yields the most recent first.
+        while let Some(key) = keys.pop() {
+            let value = self.map.get_with(&key, |value: &Value<K, V>| {
+                match self.invalidator.is_invalid(value) {
+                    true => None,
+                    false => {
+                        let copy = Arc::new(value.value.as_ref().clone());
+                        Some(Value { value: copy, ..value.clone() })
+                    }
+                }
+            });
+            if let Some(value) = value.flatten() {
+                other.absorb_entry(key, value, &mut Conflict::KeepSelf)?;
+            }
+        }
+
+        Ok(other)
+    }
+
+    /// Copy the most recently used entries of `other` into this cache, as many
+    /// as fit within this cache's remaining `max_entries` and `max_memory`,
+    /// say to carry the hot set over to a resized cache. Entries are set least
+    /// recently used first, so that they retain their relative recency, along
+    /// with their age, hit count, cost and priority. Values are shared with
+    /// `other`, not copied. Keys already in this cache are left as is, and
+    /// entries are subject to this cache's admission callback. Return the
+    /// number of entries copied.
+    pub fn warm_from<H2, M2>(&mut self, other: &Lru<K, V, H2, M2>) -> Result<usize>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+        M2: Backend<K, Value<K, V>, H2>,
+    {
+        let mut keys = vec![];
+        other.list.walk(|node| {
+            if !node.is_deleted() {
+                keys.push(node.to_key().clone())
+            }
+            true
+        });
+
+        // pick the most recently used entries that fit.
+        let mut n_entries = self.max_entries().saturating_sub(self.len());
+        let cur_memory = self.cur_memory.load(SeqCst);
+        let mut memory = self.max_memory.map(|m| m.saturating_sub(cur_memory));
+        let mut entries = vec![];
+        for key in keys.into_iter() {
+            if n_entries == 0 {
+                break;
+            }
+            let value = other.map.get_with(&key, |value: &Value<K, V>| {
+                match other.invalidator.is_invalid(value) {
+                    true => None,
+                    false => Some(value.clone()),
+                }
+            });
+            let value = match value.flatten() {
+                Some(value) => value,
+                None => continue,
+            };
+            let weight = (self.weigher)(&key, value.value.as_ref());
+            match memory.as_mut() {
+                Some(memory) if weight > *memory => break,
+                Some(memory) => *memory -= weight,
+                None => (),
+            }
+            n_entries -= 1;
+            entries.push((key, value));
+        }
+
+        // least recently used first, so that they retain their recency.
+        let mut n = 0;
+        for (key, value) in entries.into_iter().rev() {
+            if self.absorb_entry(key, value, &mut Conflict::KeepSelf)? {
+                n += 1;
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Extend the value of `key` with `tail`, treating a missing entry as an
+    /// empty value. The stored value is extended in place, and copied only
+    /// when concurrent gets hold on to it, they keep seeing the old value.
+    /// With an admission callback, a `max_entry_weight`, a non-default
+    /// [Update], or a backend that can't update in place, the extended value
+    /// is built from a copy and swapped in only once admitted, if rejected the
+    /// old one is left as is. Either way the entry retains its hit count.
+    /// Applied like [Lru::upsert], one after the other for the same key.
+    pub fn append<T>(&mut self, key: K, tail: &T) -> Result<()>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone + Default + Append<T>,
+        H: BuildHasher,
+        T: ?Sized,
+    {
+        self.check_frozen()?;
+
+        let stripes = Arc::clone(&self.stripes);
+        let _guard = stripes.lock(self.key_hash(&key));
+
+        let in_place = self.admit.is_none()
+            && self.max_entry_weight.is_none()
+            && self.update == Update::default();
+        if in_place {
+            let (list, weigher) = (&self.list, &self.weigher);
+            let (invalidator, n_versions) = (&self.invalidator, &self.inner.n_versions);
+            // holding the map's lock, entry's access node can be swapped as
+            // is, without racing gets.
+            let res = self.map.update_with(&key, |value: &mut Value<K, V>| {
+                let version = value.version.load(SeqCst);
+                if version == LOCKED || invalidator.is_invalid(value) {
+                    return Ok(None);
+                }
+                let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+                let nptr = list.prepend(value.key.clone())?;
+
+                Arc::make_mut(&mut value.value).append(tail);
+                let weight = weigher(&value.key, value.value.as_ref());
+                let old_weight = mem::replace(&mut value.weight, weight);
+                value.version.store(n_versions.fetch_add(1, SeqCst) + 1, SeqCst);
+                value.born = now;
+                value.stale.store(false, SeqCst);
+                value.touch_access(now);
+
+                let optr = value.access.swap(nptr, SeqCst);
+                unsafe { optr.as_ref().unwrap() }.delete();
+                Ok(Some((old_weight, weight)))
+            });
+            if let Some((old_weight, weight)) = res.transpose()?.flatten() {
+                self.cur_memory.fetch_add(weight, SeqCst);
+                self.cur_memory.fetch_sub(old_weight, SeqCst);
+                self.inner.counters.n_sets.fetch_add(1, SeqCst);
+                self.waiters.notify();
+                return Ok(());
+            }
+        }
+
+        let (old, version) = match self.claim(&key) {
+            Some((old, version)) => (Some(old), Some(version)),
+            None => (None, None),
+        };
+        let mut value = old.as_deref().cloned().unwrap_or_default();
+        value.append(tail);
+
+        let value = self
+            .admit(&key, &value)
+            .and_then(|demoted| self.new_value(key.clone(), Arc::new(value), demoted));
+        let value = match value {
+            Ok(value) => value,
+            Err(err) => {
+                if let Some(version) = version {
+                    self.unclaim(&key, version);
+                }
+                return Err(err);
+            }
+        };
+        self.map.get_with(&key, |old: &Value<K, V>| {
+            value.n_access.store(old.n_access.load(SeqCst), SeqCst);
+            value.freq.store(old.freq.load(SeqCst), SeqCst);
+        });
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        self.insert_locked(key, value, self.update);
+        Ok(())
+    }
+}
+
+impl<K, V, H, M> Lru<K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+{
+    // lookup `key`, record the access and apply `callb` on the entry.
+    pub(crate) fn read<Q, F, R>(&self, key: &Q, mut callb: F) -> Result<Option<R>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: ToOwned<Owned = K> + Eq + Hash,
+        H: BuildHasher,
+        F: FnMut(&Value<K, V>) -> R,
+    {
+        self.inner.counters.n_gets.fetch_add(1, SeqCst);
+
+        let hash = match self.access_sample {
+            1 => 0,
+            _ => self.key_hash(key),
+        };
+
+        let val = self.map.get_with(key, |value: &Value<K, V>| {
+            if self.invalidator.is_invalid(value) {
+                return Ok(None);
+            }
+
+            let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+            value.touch_freq(crate::freq_epoch(now, self.freq_decay));
+            value.touch_access(now);
+
+            let n = value.n_access.fetch_add(1, SeqCst) as u64;
+            if self.policy == Policy::ClockPro {
+                value.clock.fetch_or(crate::CLOCK_REF, SeqCst);
+            } else if hash.wrapping_add(n) % self.access_sample == 0 {
+                self.access(value)?;
+            }
+            Ok(Some(callb(value)))
+        });
+
+        Ok(val.transpose()?.flatten())
+    }
+
+    // read-modify-write `key`, holding its stripe lock, with the current entry
+    // claimed so that set_if_version and invalidate_tag keep off it.
+    fn update<F>(&mut self, key: K, callb: F) -> Result<()>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+        F: FnOnce(Option<&V>) -> Result<V>,
+    {
+        let stripes = Arc::clone(&self.stripes);
+        let _guard = stripes.lock(self.key_hash(&key));
+
+        let (old, version) = match self.claim(&key) {
+            Some((old, version)) => (Some(old), Some(version)),
+            None => (None, None),
+        };
+
+        let value = callb(old.as_deref()).and_then(|value| {
+            let demoted = self.admit(&key, &value)?;
+            Ok((value, demoted))
+        });
+        let (value, demoted) = match value {
+            Ok(res) => res,
+            Err(err) => {
+                if let Some(version) = version {
+                    self.unclaim(&key, version);
+                }
+                return Err(err);
+            }
+        };
+
+        let value = match self.new_value(key.clone(), Arc::new(value), demoted) {
+            Ok(value) => value,
+            Err(err) => {
+                if let Some(version) = version {
+                    self.unclaim(&key, version);
+                }
+                return Err(err);
+            }
+        };
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+
+        self.insert_locked(key, value, self.update);
+        Ok(())
+    }
+
+    // claim the entry for `key`, waiting out other claimants, and return its
+    // value and version. Return None if entry is missing or invalidated.
+    fn claim(&self, key: &K) -> Option<(Arc<V>, u64)>
+    where
+        K: Eq + Hash,
+    {
+        loop {
+            let claimed = self.map.get_with(key, |value: &Value<K, V>| {
+                let version = value.version.load(SeqCst);
+                if self.invalidator.is_invalid(value) {
+                    Some(None)
+                } else if version == LOCKED {
+                    None // claimed by set_if_version or invalidate_tag, retry.
+                } else {
+                    let res =
+                        value.version.compare_exchange(version, LOCKED, SeqCst, SeqCst);
+                    res.ok().map(|version| Some((Arc::clone(&value.value), version)))
+                }
+            });
+            match claimed {
+                Some(None) => thread::yield_now(),
+                Some(Some(claimed)) => break claimed,
+                None => break None,
+            }
+        }
+    }
+
+    // release a claim, restoring the entry's `version`.
+    fn unclaim(&self, key: &K, version: u64)
+    where
+        K: Eq + Hash,
+    {
+        self.map.get_with(key, |value: &Value<K, V>| {
+            value.version.compare_exchange(LOCKED, version, SeqCst, SeqCst)
+        });
+    }
+
+    // apply admission callback, return whether the entry is demoted. Fail
+    // with `Frozen` if the cache is frozen, and with `Oversized` if the entry
+    // exceeds `max_entry_weight`.
+    fn admit(&self, key: &K, value: &V) -> Result<bool> {
+        self.check_frozen()?;
+        if let Some(max) = self.max_entry_weight {
+            let weight = (self.weigher)(key, value);
+            if weight > max {
+                self.inner.counters.n_oversized.fetch_add(1, SeqCst);
+                err_at!(Oversized, msg: "entry weight {} > {}", weight, max)?;
+            }
+        }
+        match self.admit.as_ref().map(|admit| admit(key, value)) {
+            Some(Admission::Reject) => {
+                self.inner.counters.n_rejected.fetch_add(1, SeqCst);
+                err_at!(Rejected, msg: "admission callback")
+            }
+            Some(Admission::Demote) => {
+                self.inner.counters.n_demoted.fetch_add(1, SeqCst);
+                Ok(true)
+            }
+            Some(Admission::Admit) | None => Ok(false),
+        }
+    }
+
+    // create a new entry for `key`, prepending a fresh access node.
+    fn new_value(&self, key: K, value: Arc<V>, demoted: bool) -> Result<Value<K, V>>
+    where
+        K: Clone + Hash,
+        H: BuildHasher,
+    {
+        let mut clock = 0;
+        if let Some(ghost) = self.inner.ghost.as_ref() {
+            if ghost.hit(self.key_hash(&key)) {
+                self.inner.counters.n_ghost_hits.fetch_add(1, SeqCst);
+                if self.policy == Policy::ClockPro {
+                    clock = crate::CLOCK_HOT;
+                }
+            }
+        }
+
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        let weight = (self.weigher)(&key, value.as_ref());
+        let value = Value {
+            value,
+            access: AtomicPtr::new(self.list.prepend(key.clone())?),
+            key,
+            n_access: AtomicUsize::new(0),
+            freq: AtomicU64::new(0),
+            last_access: AtomicU64::new(0),
+            version: AtomicU64::new(self.inner.n_versions.fetch_add(1, SeqCst) + 1),
+            born: now,
+            weight,
+            demoted,
+            stale: AtomicBool::new(false),
+            clock: AtomicU8::new(clock),
+            cost: 0,
+            priority: Priority::Normal,
+        };
+        value.touch_freq(crate::freq_epoch(now, self.freq_decay));
+        value.touch_access(now);
+
+        Ok(value)
+    }
+
+    // insert entry into the map, account for its footprint and retire the
+    // access node of the replaced entry, if any.
+    fn insert(&mut self, key: K, value: Value<K, V>) -> Option<Value<K, V>>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let update = self.update;
+        self.insert_with(key, value, update)
+    }
+
+    // same as insert, renewing age and recency of a replaced entry as per
+    // `update`.
+    fn insert_with(
+        &mut self,
+        key: K,
+        value: Value<K, V>,
+        update: Update,
+    ) -> Option<Value<K, V>>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let stripes = Arc::clone(&self.stripes);
+        let _guard = stripes.lock(self.key_hash(&key));
+
+        self.insert_locked(key, value, update)
+    }
+
+    // same as insert_with, for callers already holding the key's stripe lock.
+    fn insert_locked(
+        &mut self,
+        key: K,
+        mut value: Value<K, V>,
+        update: Update,
+    ) -> Option<Value<K, V>>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let (nptr, kept_key) = self.before_insert(&key, &mut value, update);
+        let indexed = self.prefixes.as_ref().map(|_| key.clone());
+        let old = self.map.set(key, value);
+        if let Some(key) = indexed.as_ref() {
+            self.add_prefix(key);
+        }
+        self.after_insert(old, nptr, kept_key)
+    }
+
+    // index `key` by its bytes, refer LruBuilder::prefix_index.
+    fn add_prefix(&self, key: &K)
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        if let Some(prefixes) = self.prefixes.as_ref() {
+            let is_live = |key: &K| self.map.get_with(key, |_| ()).is_some();
+            prefixes.add(self.key_hash(key), key, is_live);
+        }
+    }
+
+    // account `value` and carry over the age of the entry it replaces, as per
+    // `update`. Return its access node and, when the replaced entry's position
+    // is to be retained, the key.
+    fn before_insert(
+        &self,
+        key: &K,
+        value: &mut Value<K, V>,
+        update: Update,
+    ) -> (*mut list::Node<K>, Option<K>)
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        if !update.resets_ttl {
+            // walk lock keeps the old access node from being freed under us.
+            let walk = self.list.read_walk();
+            let old = self
+                .map
+                .get_with(key, |v| (v.born, v.stale.load(SeqCst), v.as_node().to_born()));
+            mem::drop(walk);
+            if let Some((born, stale, access_born)) = old {
+                value.born = born;
+                value.stale = AtomicBool::new(stale);
+                // max_old counts from the access node, carry its age as well.
+                let nptr = self.list.prepend_at(key.clone(), access_born);
+                let fresh = value.access.swap(nptr, SeqCst);
+                unsafe { fresh.as_ref().unwrap() }.delete();
+            }
+        }
+        self.cur_memory.fetch_add(value.weight, SeqCst);
+
+        let kept_key = match update.promotes {
+            true => None,
+            false => Some(key.clone()),
+        };
+        (value.access.load(SeqCst), kept_key)
+    }
+
+    // account the entry replaced, if any, by the value with access node `nptr`.
+    fn after_insert(
+        &self,
+        old: Option<Value<K, V>>,
+        nptr: *mut list::Node<K>,
+        kept_key: Option<K>,
+    ) -> Option<Value<K, V>>
+    where
+        K: Eq + Hash,
+        H: BuildHasher,
+    {
+        self.waiters.notify();
+        match old {
+            Some(old) => {
+                let optr = old.access.load(SeqCst);
+                let kept = match kept_key {
+                    Some(key) => self.restore_access(&key, nptr, optr),
+                    None => false,
+                };
+                if !kept {
+                    unsafe { optr.as_ref().unwrap() }.delete();
+                }
+                self.cur_memory.fetch_sub(old.weight, SeqCst);
+                Some(old)
+            }
+            None => {
+                self.cur_entries.fetch_add(1, SeqCst);
+                None
+            }
+        }
+    }
+
+    // point entry `key` back from its access node `nptr` to `optr`, of the
+    // value it replaced, retaining its position in the access list. Return
+    // false if the entry is accessed, replaced or removed in the meantime.
+    fn restore_access(
+        &self,
+        key: &K,
+        nptr: *mut list::Node<K>,
+        optr: *mut list::Node<K>,
+    ) -> bool
+    where
+        K: Eq + Hash,
+        H: BuildHasher,
+    {
+        let res = self.map.get_with(key, |value: &Value<K, V>| {
+            match value.access.compare_exchange(nptr, optr, SeqCst, SeqCst) {
+                Ok(_) => {
+                    unsafe { nptr.as_ref().unwrap() }.delete();
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+        res.unwrap_or(false)
+    }
+
+    // remove entry from the map, account for its footprint and retire its
+    // access node.
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<Value<K, V>>
+    where
+        K: Borrow<Q> + Clone,
+        Q: Eq + Hash + ?Sized,
+        H: BuildHasher,
+    {
+        let value = self.map.remove(key)?;
+        value.as_node().delete();
+        self.cur_entries.fetch_sub(1, SeqCst);
+        self.cur_memory.fetch_sub(value.weight, SeqCst);
+        Some(value)
+    }
+
+    // move the entry to the head of the access list, marking the older access
+    // node as deleted. The stored key, and not the lookup key, is carried
+    // forward, refer Lru::get_key_value.
+    fn access(&self, value: &Value<K, V>) -> Result<()>
+    where
+        K: Clone,
+    {
+        let mut retries = 0;
+        loop {
+            let optr = value.access.load(SeqCst);
+            let nptr = self.list.prepend(value.key.clone())?;
+            match value.access.compare_exchange(optr, nptr, SeqCst, SeqCst) {
+                Ok(_) => {
+                    unsafe { optr.as_ref().unwrap() }.delete();
+                    self.inner.counters.swaps.record(retries);
+                    break Ok(());
+                }
+                Err(_) => {
+                    unsafe { nptr.as_ref().unwrap() }.delete();
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    // record an access for each of `keys`, as get would, without reading
+    // their values.
+    fn touch_all(&self, keys: &[K]) -> Result<()>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let now = err_at!(Fatal, UNIX_EPOCH.elapsed())?;
+        let epoch = crate::freq_epoch(now, self.freq_decay);
+        for key in keys.iter() {
+            let res = self.map.get_with(key, |value: &Value<K, V>| {
+                if self.invalidator.is_invalid(value) {
+                    return Ok(());
+                }
+                value.touch_freq(epoch);
+                value.touch_access(now);
+                match self.policy {
+                    Policy::ClockPro => {
+                        value.clock.fetch_or(crate::CLOCK_REF, SeqCst);
+                        Ok(())
+                    }
+                    _ => self.access(value),
+                }
+            });
+            res.transpose()?;
+        }
+        Ok(())
+    }
+
+    // invoke `callb` with a record for each valid entry, least recently used
+    // first, return the number of records.
+    #[cfg(feature = "serde")]
+    fn walk_records<F>(&self, mut callb: F) -> Result<usize>
+    where
+        K: Clone + Eq + Hash,
+        V: Clone,
+        H: BuildHasher,
+        F: FnMut(&Record<K, V>) -> Result<()>,
+    {
+        export::walk_records(&self.list, &self.map, &self.invalidator, &mut callb)
+    }
+
+    // set an entry from `record`, aged relative to `now`, return false if it
+    // is rejected by the admission callback, or for its weight.
+    #[cfg(feature = "serde")]
+    fn set_record(&mut self, now: Duration, record: Record<K, V>) -> Result<bool>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+        let demoted = match self.admit(&record.key, &record.value) {
+            Ok(demoted) => demoted,
+            Err(Error::Rejected(_, _)) | Err(Error::Oversized(_, _)) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        let key = record.key;
+        let mut value = self.new_value(key.clone(), Arc::new(record.value), demoted)?;
+        let age = Duration::from_millis(record.age_ms);
+        value.born = now.checked_sub(age).unwrap_or_default();
+        value.n_access = AtomicUsize::new(record.hits);
+        self.insert(key, value);
+
+        Ok(true)
+    }
+
+    // build an empty cache with the same configuration and admission callback,
+    // without periodic snapshots, so that both don't write to the same path.
+    fn rebuild(&self) -> Self
+    where
+        K: 'static + Send + Clone + Eq + Hash,
+        V: 'static + Send + Clone,
+        H: 'static + Send + Clone + BuildHasher,
+        M: 'static,
+    {
+        let config = self.inner.config.clone();
+        #[cfg(feature = "serde")]
+        let config = LruBuilder { snapshot: None, ..config };
+        let mut lru: Self = config.build_weighed(self.hash_builder.clone(), self.weigher);
+        lru.admit = self.admit.clone();
+        lru
+    }
+
+    // set `other`, drained from another cache, resolving a conflict with the
+    // entry for `key` as per `conflict`, return false if it is not absorbed.
+    fn absorb_entry(
+        &mut self,
+        key: K,
+        other: Value<K, V>,
+        conflict: &mut Conflict<K, V>,
+    ) -> Result<bool>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher,
+    {
+        let stripes = Arc::clone(&self.stripes);
+        let _guard = stripes.lock(self.key_hash(&key));
+
+        let invalidator = Arc::clone(&self.invalidator);
+        let ours = self.map.get_with(&key, |value: &Value<K, V>| {
+            match invalidator.is_invalid(value) {
+                true => None,
+                false => Some((Arc::clone(&value.value), value.born)),
+            }
+        });
+        let (value, retain) = match (ours.flatten(), conflict) {
+            (None, _) => (other.value, true),
+            (Some(_), Conflict::KeepSelf) => return Ok(false),
+            (Some((_, born)), Conflict::KeepNewest) if born >= other.born => {
+                return Ok(false)
+            }
+            (Some(_), Conflict::KeepNewest) => (other.value, true),
+            (Some((ours, _)), Conflict::Resolve(resolve)) => {
+                let value = resolve(&key, ours.as_ref(), other.value.as_ref());
+                (Arc::new(value), false)
+            }
+        };
+
+        self.inner.counters.n_sets.fetch_add(1, SeqCst);
+        let demoted = match self.admit(&key, value.as_ref()) {
+            Ok(demoted) => demoted,
+            Err(Error::Rejected(_, _)) | Err(Error::Oversized(_, _)) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        let mut value = self.new_value(key.clone(), value, demoted)?;
+        if retain {
+            value.born = other.born;
+            value.n_access = AtomicUsize::new(other.n_access.load(SeqCst));
+            value.cost = other.cost;
+            value.priority = other.priority;
+        }
+        self.insert_locked(key, value, self.update);
+
+        Ok(true)
+    }
+
+    fn check_frozen(&self) -> Result<()> {
+        match self.inner.frozen.load(SeqCst) {
+            true => err_at!(Frozen, msg: "cache is frozen"),
+            false => Ok(()),
+        }
+    }
+
+    fn key_hash<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash + ?Sized,
+        H: BuildHasher,
+    {
+        self.hash_builder.hash_one(key)
+    }
+}
+
+/// Resumable position of [Lru::scan].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cursor {
+    from: u64, // hash of keys to resume from.
+    done: bool,
+}
+
+impl Cursor {
+    /// Return whether the scan is complete.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Iterator returned by [Lru::iter_older_than], yield `(key, value, age)`.
+pub struct OlderThan<'a, K, V, H, M> {
+    lru: &'a Lru<K, V, H, M>,
+    keys: Vec<K>, // most recent access first.
+    age: Duration,
+    now: Duration,
+}
+
+impl<'a, K, V, H, M> Iterator for OlderThan<'a, K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+    K: Eq + Hash,
+    V: Clone,
+    H: BuildHasher,
+{
+    type Item = (K, V, Duration);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(key) = self.keys.pop() {
+            let (now, age) = (self.now, self.age);
+            let invalidator = &self.lru.invalidator;
+            let item = self.lru.map.get_with(&key, |value: &Value<K, V>| {
+                let entry_age = now.checked_sub(value.born).unwrap_or_default();
+                match entry_age > age && !invalidator.is_invalid(value) {
+                    true => Some((value.value.as_ref().clone(), entry_age)),
+                    false => None,
+                }
+            });
+            if let Some(Some((value, age))) = item {
+                return Some((key, value, age));
+            }
+        }
+        None
+    }
+}
+
+/// Iterator returned by [Lru::iter_touch], yield `(key, value, age)`.
+pub struct Touched<'a, K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+    K: Clone + Eq + Hash,
+    H: BuildHasher,
+{
+    iter: OlderThan<'a, K, V, H, M>,
+    batch: Vec<K>, // yielded keys, yet to be touched.
+}
+
+impl<'a, K, V, H, M> Touched<'a, K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+    K: Clone + Eq + Hash,
+    H: BuildHasher,
+{
+    fn flush(&mut self) {
+        // entries are yielded irrespective of whether their access could
+        // be recorded, which fails only if the clock is broken.
+        self.iter.lru.touch_all(&self.batch).ok();
+        self.batch.clear();
+    }
+}
+
+impl<'a, K, V, H, M> Iterator for Touched<'a, K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher,
+{
+    type Item = (K, V, Duration);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter.next() {
+            Some((key, value, age)) => {
+                self.batch.push(key.clone());
+                if self.batch.len() >= TOUCH_BATCH {
+                    self.flush();
+                }
+                Some((key, value, age))
+            }
+            None => {
+                self.flush();
+                None
+            }
+        }
+    }
+}
+
+impl<'a, K, V, H, M> Drop for Touched<'a, K, V, H, M>
+where
+    M: Backend<K, Value<K, V>, H>,
+    K: Clone + Eq + Hash,
+    H: BuildHasher,
+{
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// snapshot counters, reading each one via `read`.
+pub(crate) fn to_stats_with<K>(
+    counters: &evictor::Counters,
+    list: &list::List<K>,
+    max_entries: &AtomicUsize,
+    read: fn(&AtomicUsize) -> usize,
+) -> Stats {
+    Stats {
+        n_gets: read(&counters.n_gets),
+        n_sets: read(&counters.n_sets),
+        n_rejected: read(&counters.n_rejected),
+        n_oversized: read(&counters.n_oversized),
+        n_demoted: read(&counters.n_demoted),
+        n_evicted: read(&counters.n_evicted),
+        n_deleted: read(&counters.n_deleted),
+        n_older: read(&counters.n_older),
+        n_ghost_hits: read(&counters.n_ghost_hits),
+        n_grows: read(&counters.n_grows),
+        n_shrinks: read(&counters.n_shrinks),
+        n_pressure: read(&counters.n_pressure),
+        n_invalidated: read(&counters.n_invalidated),
+        n_expire_callbacks: read(&counters.n_expire_callbacks),
+        n_stale: read(&counters.n_stale),
+        n_costed: read(&counters.n_costed),
+        n_prioritized: read(&counters.n_prioritized),
+        n_quota: read(&counters.n_quota),
+        n_leak_alerts: read(&counters.n_leak_alerts),
+        n_handoff: read(&counters.n_handoff),
+        n_handoff_dropped: read(&counters.n_handoff_dropped),
+        time_paused: Duration::from_micros(read(&counters.time_paused) as u64),
+        passes: counters.passes.to_stats(read),
+        last_pass: counters.last_pass_stats.to_stats(|c| c.load(SeqCst)),
+        max_entries: max_entries.load(SeqCst),
+        n_prepends: read(&list.prepends.n_ops),
+        n_prepend_retries: read(&list.prepends.n_retries),
+        max_prepend_streak: read(&list.prepends.max_streak),
+        n_swaps: read(&counters.swaps.n_ops),
+        n_swap_retries: read(&counters.swaps.n_retries),
+        max_swap_streak: read(&counters.swaps.max_streak),
+    }
+}
+
+/// Health of the cache, returned by [Lru::health].
+#[derive(Debug)]
+pub struct Health {
+    /// whether the evictor is running, false once it has exited or failed.
+    pub evictor_alive: bool,
+    /// time since the evictor completed its last pass, None if yet to.
+    pub since_last_pass: Option<Duration>,
+    /// number of entries above the current count-limit.
+    pub entries_overshoot: usize,
+    /// memory footprint, in bytes, above `max_memory`.
+    pub memory_overshoot: usize,
+    /// number of deleted access nodes, yet to be freed by the evictor.
+    pub n_tombstones: usize,
+}
+
+#[derive(Debug)]
+pub struct Stats {
+    pub n_gets: usize,
+    pub n_sets: usize,
+    /// number of sets rejected, or demoted, by the admission callback.
+    pub n_rejected: usize,
+    pub n_demoted: usize,
+    /// number of sets rejected for exceeding `max_entry_weight`.
+    pub n_oversized: usize,
+    pub n_evicted: usize,
+    pub n_deleted: usize,
+    pub n_older: usize,
+    /// number of sets on keys that were recently evicted for capacity.
+    pub n_ghost_hits: usize,
+    /// number of times `max_entries` was grown or shrunk, refer `adaptive_cap`.
+    pub n_grows: usize,
+    pub n_shrinks: usize,
+    /// number of times host memory pressure was detected, refer `pressure`.
+    pub n_pressure: usize,
+    /// number of entries removed by the evictor via `invalidate_entries_if`.
+    pub n_invalidated: usize,
+    /// number of callbacks fired on expiry, refer `on_expire`.
+    pub n_expire_callbacks: usize,
+    /// number of entries flagged stale, refer `soft_ttl`.
+    pub n_stale: usize,
+    /// number of sets with a cost hint, refer [Lru::set_with_cost].
+    pub n_costed: usize,
+    /// number of sets with priority other than `Normal`, refer
+    /// [Lru::set_with_priority].
+    pub n_prioritized: usize,
+    /// number of entries removed for exceeding their group's quota, refer
+    /// [LruBuilder::quota].
+    pub n_quota: usize,
+    /// number of times access nodes were found leaking, refer `leak_threshold`.
+    pub n_leak_alerts: usize,
+    /// number of evicted entries sent to, or dropped for a full, handoff
+    /// channel, refer [LruBuilder::evict_to].
+    pub n_handoff: usize,
+    pub n_handoff_dropped: usize,
+    /// time spent with eviction paused, refer [Lru::pause_eviction], across
+    /// pauses that were resumed.
+    pub time_paused: Duration,
+    /// work done by the evictor across all passes.
+    pub passes: PassStats,
+    /// work done by the evictor in its last pass, not affected by
+    /// [Lru::reset_stats].
+    pub last_pass: PassStats,
+    /// current count-limit, differs from configured `max_entries` when adaptive.
+    pub max_entries: usize,
+    /// number of nodes prepended to the access list, with the number of failed
+    /// CAS on the list head, and the longest run of such failures.
+    pub n_prepends: usize,
+    pub n_prepend_retries: usize,
+    pub max_prepend_streak: usize,
+    /// number of access node swaps on get, with the number of failed CAS on the
+    /// entry's access pointer, and the longest run of such failures.
+    pub n_swaps: usize,
+    pub n_swap_retries: usize,
+    pub max_swap_streak: usize,
+}
+
+/// Work done by evictor passes, refer [Stats::passes] and [Stats::last_pass].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PassStats {
+    pub n_passes: usize,
+    /// number of access nodes walked.
+    pub n_scanned: usize,
+    /// number of access nodes of removed, or replaced, entries freed.
+    pub n_reclaimed: usize,
+    /// number of entries removed, for limits, expiry or invalidation.
+    pub n_evicted: usize,
+    pub elapsed: Duration,
+}
+
+impl PassStats {
+    /// Return the number of access nodes walked per entry removed, a high
+    /// ratio is a sign of passes spent on walking live entries and tombstones.
+    pub fn scan_ratio(&self) -> f64 {
+        (self.n_scanned as f64) / (std::cmp::max(self.n_evicted, 1) as f64)
+    }
 }
 
 #[cfg(test)]
@@ -1,12 +1,25 @@
 use arbitrary::{self, unstructured::Unstructured, Arbitrary};
 use rand::{self, prelude::random, rngs::StdRng, Rng, SeedableRng};
 
-use std::{fmt, hash::Hash, thread};
+use std::{collections::BTreeMap, fmt, hash::Hash, sync::Arc, thread};
 
-use crate::{dbs, llrb};
+use super::*;
+
+// reference index, every key loaded into the cache is looked up here.
+type Index<K> = Arc<BTreeMap<K, u128>>;
 
 macro_rules! test_code {
     ($seed:expr, $keytype:ty) => {{
+        test_code!($seed, $keytype, build: LruBuilder::default().build_default())
+    }};
+    ($seed:expr, $keytype:ty, $map:ident) => {{
+        type Map = crate::$map<$keytype, Value<$keytype, u128>, DefaultHasher>;
+        let builder = LruBuilder::default();
+        let lru: Lru<$keytype, u128, DefaultHasher, Map> =
+            builder.build_backend(DefaultHasher::default());
+        test_code!($seed, $keytype, build: lru)
+    }};
+    ($seed:expr, $keytype:ty, build: $lru:expr) => {{
         let mut rng = StdRng::seed_from_u64($seed);
 
         let n_threads = [1, 2, 4, 8, 16, 32, 64][rng.gen::<usize>() % 7];
@@ -37,7 +50,7 @@ macro_rules! test_code {
         );
 
         let index = populate_primary_index::<$keytype>($seed, n);
-        let lru = LruBuilder::default().build(cmap::DefaultHasher::new());
+        let lru = $lru;
         println!("test_lru_{} loaded index ...", stringify!($keytype));
 
         let mut handles = vec![];
@@ -64,7 +77,7 @@ macro_rules! test_code {
             assert!(keys.len() == m, "{} != {}", keys.len(), m);
 
             let h = thread::spawn(move || {
-                with_lru::<$keytype>(thread_id, seed, index, lru, keys, n_ops)
+                with_lru::<$keytype, _>(thread_id, seed, index, lru, keys, n_ops)
             });
             handles.push(h);
         }
@@ -105,6 +118,26 @@ fn test_lru_u32() {
     test_code!(seed, u32);
 }
 
+// same as test_lru_u16, with each of the non-default backends.
+#[test]
+fn test_lru_sharded() {
+    let seed: u64 = [12552994332855700723, random()][random::<usize>() % 2];
+    test_code!(seed, u16, Sharded);
+}
+
+#[test]
+fn test_lru_tiered() {
+    let seed: u64 = [12552994332855700723, random()][random::<usize>() % 2];
+    test_code!(seed, u16, Tiered);
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn test_lru_dashmap() {
+    let seed: u64 = [12552994332855700723, random()][random::<usize>() % 2];
+    test_code!(seed, u16, DashMap);
+}
+
 #[test]
 fn test_lru_u128() {
     let seed: u64 = [9393685787223080701, random()][random::<usize>() % 2];
@@ -112,16 +145,17 @@ fn test_lru_u128() {
     test_code!(seed, u128);
 }
 
-fn with_lru<K>(
+fn with_lru<K, M>(
     _thread_id: usize,
     seed: u64,
-    index: llrb::Index<K, u128>,
-    mut lru: Lru<K, u128>,
+    index: Index<K>,
+    mut lru: Lru<K, u128, DefaultHasher, M>,
     keys: Vec<K>,
     n_ops: usize,
 ) -> CacheStat
 where
-    K: Copy + Clone + PartialEq + Ord + Hash + fmt::Display + fmt::Debug,
+    K: Copy + Clone + PartialEq + Ord + Hash + fmt::Display + fmt::Debug + Send + Sync,
+    M: Backend<K, Value<K, u128>, DefaultHasher>,
 {
     let mut rng = StdRng::seed_from_u64(seed);
     let mut stats = CacheStat::default();
@@ -129,17 +163,17 @@ where
     for _i in 0..n_ops {
         let key = keys[rng.gen::<usize>() % keys.len()];
         // println!("thread-{} {}", _thread_id, key);
-        match lru.get(&key) {
+        match lru.get(&key).unwrap() {
             Some(value) => {
                 // println!("thread-{} get-ok key:{}", _thread_id, key);
                 let ref_value = index.get(&key).unwrap();
-                assert_eq!(ref_value.to_value().unwrap(), value);
+                assert_eq!(*ref_value, value);
                 stats.hits += 1;
             }
             None => {
                 // println!("thread-{} get-no key:{}", _thread_id, key);
-                let ref_value = index.get(&key).unwrap().to_value().unwrap();
-                lru.set(key, ref_value);
+                let ref_value = *index.get(&key).unwrap();
+                lru.set(key, ref_value).unwrap();
                 stats.misses += 1;
             }
         }
@@ -148,24 +182,24 @@ where
     stats
 }
 
-fn populate_primary_index<K>(seed: u64, n: usize) -> llrb::Index<K, u128>
+fn populate_primary_index<K>(seed: u64, n: usize) -> Index<K>
 where
-    K: Clone + Ord + dbs::Footprint,
+    K: Clone + Ord,
     rand::distributions::Standard: rand::distributions::Distribution<K>,
 {
     let mut rng = StdRng::seed_from_u64(seed);
-    let index: llrb::Index<K, u128> = llrb::Index::new("primary_index", true);
+    let mut index: BTreeMap<K, u128> = BTreeMap::new();
     for _ in 0..n {
         loop {
             let key: K = rng.gen();
             let value: u128 = rng.gen::<u128>();
-            if let dbs::Wr { old_entry: None, .. } = index.insert(key, value).unwrap() {
+            if index.insert(key, value).is_none() {
                 break;
             }
         }
     }
 
-    index
+    Arc::new(index)
 }
 
 fn access_keys<K>(
@@ -173,7 +207,7 @@ fn access_keys<K>(
     n_threads: usize,
     seed: u64,
     access_type: AccessType,
-    index: llrb::Index<K, u128>,
+    index: Index<K>,
     m: usize,
 ) -> Vec<K>
 where
@@ -181,16 +215,14 @@ where
 {
     let mut rng = StdRng::seed_from_u64(seed);
 
-    let iter = index.iter().unwrap();
+    let iter = index.keys().cloned();
     match access_type {
-        AccessType::Inclusive => {
-            iter.step_by(n_threads).take(m).map(|e| e.to_key()).collect()
-        }
+        AccessType::Inclusive => iter.step_by(n_threads).take(m).collect(),
         AccessType::Exclusive => {
-            iter.skip(thread_id).step_by(n_threads).take(m).map(|e| e.to_key()).collect()
+            iter.skip(thread_id).step_by(n_threads).take(m).collect()
         }
         AccessType::Overlap => {
-            let keys_set: Vec<K> = iter.take(m * n_threads).map(|e| e.to_key()).collect();
+            let keys_set: Vec<K> = iter.take(m * n_threads).collect();
             let mut keys = vec![];
             while keys.len() < m {
                 let off = rng.gen::<usize>() % keys_set.len();
@@ -201,7 +233,7 @@ where
             keys
         }
         AccessType::Random => {
-            let keys_set: Vec<K> = iter.map(|e| e.to_key()).collect();
+            let keys_set: Vec<K> = iter.collect();
             let mut keys = vec![];
             while keys.len() < m {
                 let off = rng.gen::<usize>() % keys_set.len();
@@ -228,23 +260,1514 @@ struct CacheStat {
     hits: usize,
 }
 
-//fn validate(
-//    statss: Vec<CacheStat>,
-//    stats: crate::clru::Stats,
-//    n_threads: usize,
-//    n_ops: usize,
-//) {
-//    let mut n_misses = 0;
-//    for stats in statss.iter() {
-//        n_misses += stats.misses;
-//        assert_eq!(stats.misses + stats.hits, n_ops);
-//    }
-//
-//    assert_eq!(n_ops * n_threads, stats.n_gets);
-//    assert_eq!(n_misses, stats.n_sets);
-//
-//    assert_eq!(
-//        n_ops * n_threads,
-//        stats.n_evicted + stats.n_deleted + stats.n_access_gc
-//    )
-//}
+fn validate(statss: Vec<CacheStat>, stats: Stats, n_threads: usize, n_ops: usize) {
+    let mut n_misses = 0;
+    for stats in statss.iter() {
+        n_misses += stats.misses;
+        assert_eq!(stats.misses + stats.hits, n_ops);
+    }
+
+    assert_eq!(n_ops * n_threads, stats.n_gets);
+    assert_eq!(n_misses, stats.n_sets);
+}
+
+#[test]
+fn test_warm_from() {
+    let mut src: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..10 {
+        src.set(key, key * 10).unwrap();
+    }
+    src.get(&2).unwrap();
+
+    let mut dst: Lru<u64, u64> = {
+        let builder = LruBuilder { max_entries: 4, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    assert_eq!(dst.warm_from(&src).unwrap(), 4);
+    assert_eq!(recency(&dst), vec![7, 8, 9, 2]);
+    assert_eq!(dst.get(&9).unwrap(), Some(90));
+    assert_eq!(dst.get(&0).unwrap(), None);
+}
+
+// keys of `lru`, least recently used first.
+fn recency<K, V>(lru: &Lru<K, V>) -> Vec<K>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    V: Clone + Send + Sync,
+{
+    thread::sleep(std::time::Duration::from_millis(1));
+    let iter = lru.iter_older_than(std::time::Duration::default()).unwrap();
+    iter.map(|(key, _, _)| key).collect()
+}
+
+#[test]
+fn test_absorb() {
+    let mut src: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..5 {
+        src.set(key, key * 10).unwrap();
+    }
+    src.get(&1).unwrap();
+
+    let mut dst: Lru<u64, u64> = LruBuilder::default().build_default();
+    dst.set(100, 1000).unwrap();
+    assert_eq!(dst.absorb(src, Conflict::KeepSelf).unwrap(), 5);
+    assert_eq!(recency(&dst), vec![100, 0, 2, 3, 4, 1]);
+}
+
+#[test]
+fn test_split_off() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..6 {
+        lru.set(key, key * 10).unwrap();
+    }
+    lru.get(&0).unwrap();
+
+    let other = lru.split_off(|key, _| key % 2 == 0).unwrap();
+    assert_eq!(recency(&other), vec![2, 4, 0]);
+    assert_eq!(recency(&lru), vec![1, 3, 5]);
+}
+
+#[test]
+fn test_deep_clone() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..6 {
+        lru.set(key, key * 10).unwrap();
+    }
+    lru.get(&0).unwrap();
+
+    let copy = lru.deep_clone().unwrap();
+    let (key, value, _) = copy.peek_lru().unwrap().unwrap();
+    assert_eq!((key, value), (1, 10));
+    let (lkey, lvalue, _) = lru.peek_lru().unwrap().unwrap();
+    assert_eq!((key, value), (lkey, lvalue));
+    assert_eq!(recency(&copy), recency(&lru));
+
+    lru.set(1, 11).unwrap();
+    assert_eq!(copy.get(&1).unwrap(), Some(10));
+}
+
+#[test]
+fn test_append_rejected() {
+    let lru: Lru<u64, String> = LruBuilder::default().build_default();
+    let mut lru = lru.admission(|_, value: &String| match value.len() > 8 {
+        true => Admission::Reject,
+        false => Admission::Admit,
+    });
+
+    lru.append(1, "hello").unwrap();
+    lru.get(&1).unwrap();
+    let (_, version) = lru.get_versioned(&1).unwrap().unwrap();
+
+    match lru.append(1, " world") {
+        Err(Error::Rejected(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    let (value, ver) = lru.get_versioned(&1).unwrap().unwrap();
+    assert_eq!((value.as_str(), ver), ("hello", version));
+
+    lru.append(1, "!").unwrap();
+    assert_eq!(lru.get(&1).unwrap().unwrap(), "hello!");
+    assert_eq!(lru.len(), 1);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_pressure_first_poll() {
+    use crate::pressure::Pressure;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("clru-pressure-{}", random::<u64>()));
+    fs::create_dir_all(&dir).unwrap();
+    let (events, psi) = (dir.join("memory.events"), dir.join("memory.pressure"));
+    fs::write(&events, "low 0\nhigh 5\nmax 2\noom 0\n").unwrap();
+    fs::write(&psi, "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").unwrap();
+
+    let pressure = crate::CgroupPressure::from_files(events.clone(), psi);
+    assert!(!pressure.is_under_pressure(), "historical events reported");
+    fs::write(&events, "low 0\nhigh 6\nmax 2\noom 0\n").unwrap();
+    assert!(pressure.is_under_pressure());
+    assert!(!pressure.is_under_pressure());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+#[should_panic]
+fn test_pressure_floor_unset() {
+    let builder = LruBuilder {
+        pressure: Some(Arc::new(crate::CgroupPressure::default())),
+        ..LruBuilder::default()
+    };
+    let _lru: Lru<u64, u64> = builder.build_default();
+}
+
+#[test]
+fn test_dedup_purge() {
+    let table: crate::dedup::Table<u64> = crate::dedup::Table::default();
+    for value in 0..10_000_u64 {
+        let shared = table.intern(value, value);
+        assert_eq!(*shared, value);
+    }
+    assert!(table.len() <= 128, "{}", table.len());
+
+    let held: Vec<Arc<u64>> = (0..1000).map(|value| table.intern(value, value)).collect();
+    for value in 1000..10_000_u64 {
+        table.intern(value, value);
+    }
+    assert!(table.len() >= held.len(), "{}", table.len());
+    assert!(table.len() <= 2 * held.len() + 128, "{}", table.len());
+    assert!(Arc::ptr_eq(&held[7], &table.intern(7, 7)));
+}
+
+#[test]
+fn test_update_keeps_max_old() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder {
+            max_old: Some(Duration::from_millis(400)),
+            update_resets_ttl: false,
+            ..LruBuilder::default()
+        };
+        builder.build_default()
+    };
+
+    lru.set(1, 10).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    lru.set(1, 11).unwrap();
+    for key in 100..110 {
+        lru.set(key, key).unwrap();
+    }
+    thread::sleep(Duration::from_millis(300));
+
+    let keys = recency(&lru);
+    assert!(!keys.contains(&1), "{:?}", keys);
+    assert_eq!(keys.len(), 10, "{:?}", keys);
+}
+
+#[test]
+fn test_ghost() {
+    let ghost = crate::ghost::Ghost::new(4);
+    for hash in 0..4 {
+        ghost.push(hash);
+    }
+    assert!(ghost.hit(1));
+    assert!(!ghost.hit(1), "ghost entries are counted once");
+
+    // re-pushed hash outlives the stale copy ahead of it in the fifo.
+    ghost.push(1);
+    for hash in 10..13 {
+        ghost.push(hash);
+    }
+    assert!(!ghost.hit(0));
+    assert!(ghost.hit(1));
+    assert!(ghost.hit(12));
+}
+
+#[test]
+fn test_ghost_resize() {
+    let ghost = crate::ghost::Ghost::new(8);
+    for hash in 0..8 {
+        ghost.push(hash);
+    }
+    ghost.resize(3);
+    assert!(!ghost.hit(4));
+    assert!(ghost.hit(5));
+
+    ghost.push(20);
+    ghost.push(21);
+    assert!(!ghost.hit(6));
+    assert!(ghost.hit(7));
+    assert!(ghost.hit(20));
+}
+
+#[test]
+fn test_pinned_spared() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder { max_entries: 8, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    lru.set(0, 0).unwrap();
+    lru.pin(0);
+    for key in 1..64 {
+        lru.set(key, key).unwrap();
+    }
+
+    // evictor leaves the few most recent access nodes alone.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 16 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(lru.len() <= 16, "{}", lru.len());
+    assert_eq!(lru.get(&0).unwrap(), Some(0));
+}
+
+#[test]
+fn test_clear_walks_head() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..20 {
+        lru.set(key, key).unwrap();
+    }
+    lru.clear();
+    thread::sleep(Duration::from_millis(50));
+    for key in 100..110 {
+        lru.set(key, key).unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 10 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(lru.len(), 10);
+    for key in 100..110 {
+        assert_eq!(lru.get(&key).unwrap(), Some(key));
+    }
+}
+
+#[test]
+fn test_lfu_keeps_frequent() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder {
+            max_entries: 16,
+            policy: Policy::Lfu,
+            ..LruBuilder::default()
+        };
+        builder.build_default()
+    };
+    for key in 0..16 {
+        lru.set(key, key).unwrap();
+    }
+    for _ in 0..10 {
+        for key in 0..4 {
+            lru.get(&key).unwrap();
+        }
+    }
+    for key in 16..48 {
+        lru.set(key, key).unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 24 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(lru.len() <= 24, "{}", lru.len());
+    for key in 0..4 {
+        assert_eq!(lru.get(&key).unwrap(), Some(key));
+    }
+}
+
+#[test]
+fn test_older_than_invalidated() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..4 {
+        lru.set(key, key).unwrap();
+    }
+    lru.invalidate_entries_if(|key, _| *key < 2);
+    assert_eq!(recency(&lru), vec![2, 3]);
+    let (key, value, _) = lru.peek_lru().unwrap().unwrap();
+    assert_eq!((key, value), (2, 2));
+    let keys: Vec<u64> = lru.iter_touch().unwrap().map(|(key, _, _)| key).collect();
+    assert_eq!(keys, vec![2, 3]);
+
+    lru.clear();
+    assert!(lru.peek_lru().unwrap().is_none());
+    assert_eq!(recency(&lru), Vec::<u64>::new());
+}
+
+#[test]
+fn test_upsert_racing_set() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    lru.set(1, 1).unwrap();
+
+    let mut other = lru.clone();
+    let mut setter = None;
+    lru.upsert(1, |old| {
+        setter = Some(thread::spawn(move || other.set(1, 100).unwrap()));
+        thread::sleep(Duration::from_millis(100));
+        old.unwrap() + 1
+    })
+    .unwrap();
+    assert_eq!(setter.unwrap().join().unwrap(), Some(2));
+    assert_eq!(lru.get(&1).unwrap(), Some(100));
+}
+
+#[test]
+fn test_set_if_version_racing_set() {
+    use std::time::Duration;
+
+    // stall set_if_version after it has claimed the entry.
+    fn weigher(_: &u64, value: &u64) -> usize {
+        if *value == 7 {
+            thread::sleep(Duration::from_millis(100));
+        }
+        1
+    }
+
+    let builder = LruBuilder::default();
+    let mut lru: Lru<u64, u64> = builder.build_weighed(DefaultHasher::default(), weigher);
+    lru.set(1, 1).unwrap();
+    let (_, version) = lru.get_versioned(&1).unwrap().unwrap();
+
+    let mut other = lru.clone();
+    let setter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(30));
+        other.set(1, 100).unwrap()
+    });
+    assert_eq!(lru.set_if_version(1, 7, version).unwrap(), 1);
+    assert_eq!(setter.join().unwrap(), Some(7));
+    assert_eq!(lru.get(&1).unwrap(), Some(100));
+}
+
+#[test]
+fn test_swap_racing_upsert() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    lru.set(1, 1).unwrap();
+
+    let other = lru.clone();
+    let mut swapper = None;
+    lru.upsert(1, |old| {
+        swapper = Some(thread::spawn(move || other.swap(1, 100).unwrap()));
+        thread::sleep(Duration::from_millis(100));
+        old.unwrap() + 1
+    })
+    .unwrap();
+    assert_eq!(swapper.unwrap().join().unwrap(), Some(2));
+    assert_eq!(lru.get(&1).unwrap(), Some(100));
+    assert_eq!(lru.len(), 1);
+}
+
+#[test]
+fn test_quota() {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    let limit = crate::QuotaLimit { max_entries: Some(4), max_memory: None };
+    let limits: HashMap<crate::GroupId, crate::QuotaLimit> =
+        vec![(1, limit)].into_iter().collect();
+    let mut lru: Lru<u64, u64> =
+        LruBuilder::default().quota(|key: &u64| key % 2, limits).build_default();
+    for key in (1..64).step_by(2) {
+        lru.set(key, key).unwrap();
+    }
+    for key in (0..16).step_by(2) {
+        lru.set(key, key).unwrap();
+    }
+
+    let n_odd = |lru: &Lru<u64, u64>| recency(lru).iter().filter(|k| *k % 2 == 1).count();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while n_odd(&lru) > 4 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(n_odd(&lru) <= 4, "{:?}", recency(&lru));
+    assert_eq!(lru.len(), 8 + n_odd(&lru));
+}
+
+#[test]
+fn test_evict_to() {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let (tx, rx) = mpsc::sync_channel(1024);
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder { max_entries: 8, ..LruBuilder::default() };
+        builder.evict_to(tx, crate::HandoffFull::Drop).build_default()
+    };
+    for key in 0..64 {
+        lru.set(key, key * 10).unwrap();
+    }
+
+    let (key, value, reason) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!((value, reason), (key * 10, crate::EvictionReason::Capacity));
+    assert_eq!(lru.get(&key).unwrap(), None);
+}
+
+// codec for snapshot tests.
+#[cfg(feature = "serde")]
+struct Json;
+
+#[cfg(feature = "serde")]
+impl crate::Codec for Json {
+    fn encode<T: serde::Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        err_at!(FailConvert, serde_json::to_vec(value))
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        err_at!(FailConvert, serde_json::from_slice(data))
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snapshot_every() {
+    use std::time::{Duration, Instant};
+
+    let path = std::env::temp_dir().join(format!("clru-snapshot-{}", random::<u64>()));
+    let mut lru: Lru<u64, u64> = LruBuilder { snapshot_keep: 1, ..LruBuilder::default() }
+        .snapshot_every(Duration::from_millis(10), path.clone(), Json)
+        .build_default();
+    for key in 0..10 {
+        lru.set(key, key * 10).unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    while copy.len() < 10 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+        if let Ok(file) = std::fs::File::open(&path) {
+            copy = LruBuilder::default().build_default();
+            copy.load(file, &Json).unwrap();
+        }
+    }
+    assert_eq!(copy.len(), 10);
+    assert_eq!(copy.get(&7).unwrap(), Some(70));
+
+    lru.close().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_tokio_task() {
+    use std::time::{Duration, Instant};
+
+    let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+    rt.block_on(async {
+        let mut lru: Lru<u64, u64> = {
+            let builder = LruBuilder {
+                max_entries: 8,
+                tokio_task: true,
+                ..LruBuilder::default()
+            };
+            builder.build_default()
+        };
+        assert!(lru.evictor_thread().is_none());
+        for key in 0..64 {
+            lru.set(key, key).unwrap();
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while lru.len() > 16 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(lru.len() <= 16, "{}", lru.len());
+        lru.close().unwrap();
+    });
+}
+
+#[test]
+fn test_estimated_memory() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..4 {
+        lru.set(key, key).unwrap();
+    }
+    let node = std::mem::size_of::<list::Node<u64>>();
+    let entry = std::mem::size_of::<Value<u64, u64>>() + node + MAP_OVERHEAD;
+    assert_eq!(lru.estimated_memory(), 4 * (16 + entry));
+
+    // overwrite leaves a deleted access node behind, within the head window.
+    lru.set(1, 10).unwrap();
+    assert_eq!(lru.estimated_memory(), 4 * (16 + entry) + node);
+}
+
+#[cfg(all(feature = "shm", target_os = "linux"))]
+#[test]
+fn test_shm_stale_init() {
+    use std::os::unix::fs::FileExt;
+
+    match crate::ShmLru::open("/dev/null", usize::MAX / 2, 8, 8) {
+        Err(Error::FailConvert(_, _)) => (),
+        res => panic!("unexpected {:?}", res.map(|_| ())),
+    }
+
+    let path = std::env::temp_dir().join(format!("clru-shm-{}", random::<u64>()));
+    let lru = crate::ShmLru::open(&path, 16, 8, 8).unwrap();
+    lru.set(b"key", b"value").unwrap();
+    std::mem::drop(lru);
+
+    // creator died halfway through initialization, holding the lock.
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.write_at(&1_u64.to_le_bytes(), 0).unwrap();
+    file.write_at(&pid.to_le_bytes(), 32).unwrap();
+
+    let lru = crate::ShmLru::open(&path, 16, 8, 8).unwrap();
+    assert_eq!(lru.get(b"key").unwrap(), Some(b"value".to_vec()));
+    lru.set(b"next", b"value").unwrap();
+    assert_eq!(lru.len(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[should_panic]
+fn test_memory_fraction_nan() {
+    let builder = LruBuilder {
+        memory_fraction: Some(f64::NAN),
+        ..LruBuilder::default()
+    };
+    let _lru: Lru<u64, u64> = builder.build_default();
+}
+
+#[test]
+#[should_panic]
+fn test_memory_fraction_above_one() {
+    let builder = LruBuilder {
+        memory_fraction: Some(1.5),
+        ..LruBuilder::default()
+    };
+    let _lru: Lru<u64, u64> = builder.build_default();
+}
+
+#[test]
+fn test_evict_counts_head() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder { max_entries: 8, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    for key in 0..64 {
+        lru.set(key, key).unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 8 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(lru.len(), 8);
+    assert_eq!(recency(&lru), (56..64).collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_resume_eviction() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder { max_entries: 8, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    // an empty cache fits within the head of the list, passes are still seen.
+    lru.pause_eviction();
+    lru.resume_eviction().unwrap();
+
+    lru.pause_eviction();
+    for key in 0..64 {
+        lru.set(key, key).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(lru.len(), 64);
+
+    lru.resume_eviction().unwrap();
+    assert_eq!(lru.len(), 8);
+    assert!(lru.stats().time_paused >= Duration::from_millis(50));
+}
+
+#[test]
+fn test_invalidated_head_dropped() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..100 {
+        lru.set(key, key).unwrap();
+    }
+    lru.invalidate_entries_if(|key, _| *key == 99 || *key == 10);
+    assert_eq!(lru.get(&99).unwrap(), None);
+
+    // once predicates are dropped, entries at the head must be gone as well.
+    let n_passes = lru.stats().passes.n_passes;
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.stats().passes.n_passes < n_passes + 3 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!lru.invalidator.has_preds());
+    assert_eq!(lru.get(&99).unwrap(), None);
+    assert_eq!(lru.get(&10).unwrap(), None);
+    assert_eq!(lru.get(&98).unwrap(), Some(98));
+    assert_eq!(lru.len(), 98);
+}
+
+#[test]
+fn test_health_idle() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    lru.set(1, 1).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let health = lru.health();
+    assert!(health.evictor_alive);
+    let since = health.since_last_pass.unwrap();
+    assert!(since < Duration::from_secs(1), "{:?}", since);
+    assert_eq!(health.entries_overshoot, 0);
+    assert_eq!(health.memory_overshoot, 0);
+    assert_eq!(health.n_tombstones, 0);
+}
+
+#[test]
+fn test_append_in_place() {
+    type Map = crate::Sharded<u64, Value<u64, Vec<u8>>, DefaultHasher>;
+
+    let builder = LruBuilder::default();
+    let weigher = |_: &u64, value: &Vec<u8>| value.len();
+    let mut lru: Lru<u64, Vec<u8>, DefaultHasher, Map> =
+        builder.build_weighed(DefaultHasher::default(), weigher);
+
+    lru.append(1, b"hello".as_ref()).unwrap();
+    lru.get(&1).unwrap();
+    let (_, version) = lru.get_versioned(&1).unwrap().unwrap();
+    let memory = lru.cur_memory.load(SeqCst);
+    let n_access = lru.map.get_with(&1, |v| v.n_access.load(SeqCst)).unwrap();
+
+    lru.append(1, b" world".as_ref()).unwrap();
+    let (value, ver) = lru.get_versioned(&1).unwrap().unwrap();
+    assert_eq!(value, b"hello world".to_vec());
+    assert!(ver > version, "{} {}", ver, version);
+    assert_eq!(lru.cur_memory.load(SeqCst), memory + 6);
+    assert_eq!(lru.map.get_with(&1, |v| v.weight).unwrap(), 11);
+    // hit count is retained, the get above adds one.
+    let n = lru.map.get_with(&1, |v| v.n_access.load(SeqCst)).unwrap();
+    assert_eq!(n, n_access + 1);
+    assert_eq!(lru.len(), 1);
+    assert_eq!(lru.stats().n_sets, 2);
+}
+
+#[test]
+fn test_freeze() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    lru.set_tagged(1, 10, &["a"]).unwrap();
+    lru.set(2, 20).unwrap();
+    lru.set(3, 30).unwrap();
+
+    lru.freeze();
+    assert!(lru.is_frozen());
+    match lru.set(4, 40) {
+        Err(Error::Frozen(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    match lru.remove_if(&2, |_| true) {
+        Err(Error::Frozen(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    match lru.take(&3) {
+        Err(Error::Frozen(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    match lru.invalidate_tag("a") {
+        Err(Error::Frozen(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get(&1).unwrap(), Some(10));
+    assert_eq!(lru.len(), 3);
+
+    lru.unfreeze();
+    assert_eq!(lru.set(4, 40).unwrap(), None);
+    assert_eq!(lru.remove_if(&2, |v| *v == 21).unwrap(), None);
+    assert_eq!(lru.remove_if(&2, |v| *v == 20).unwrap(), Some(20));
+    assert_eq!(lru.take(&3).unwrap(), Some(30));
+    assert_eq!(lru.take(&3).unwrap(), None);
+    assert_eq!(lru.invalidate_tag("a").unwrap(), 1);
+    assert_eq!(lru.get(&1).unwrap(), None);
+    assert_eq!(lru.len(), 1);
+}
+
+#[test]
+fn test_get_entry_racing_gets() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = {
+        let max_old = Some(Duration::from_secs(3600));
+        LruBuilder { max_old, ..LruBuilder::default() }.build_default()
+    };
+    for key in 0..16 {
+        lru.set(key, key).unwrap();
+    }
+
+    // gets keep replacing the access node, for the evictor to free.
+    let reader = {
+        let lru = lru.clone();
+        thread::spawn(move || {
+            for i in 0..100_000 {
+                lru.get(&(i % 16)).unwrap();
+            }
+        })
+    };
+    for i in 0..10_000 {
+        let entry = lru.get_entry(&(i % 16)).unwrap().unwrap();
+        assert_eq!((*entry.key(), *entry.value()), (i % 16, i % 16));
+        let ttl = entry.ttl_remaining().unwrap();
+        assert!(ttl > Duration::from_secs(3500), "{:?}", ttl);
+        assert!(entry.age() < Duration::from_secs(100));
+    }
+    reader.join().unwrap();
+}
+
+#[test]
+fn test_invalidate_prefix() {
+    let builder = LruBuilder::<String, u64>::default().prefix_index();
+    let mut lru: Lru<String, u64> = builder.build_default();
+    for (i, key) in ["a/x/1", "a/x/2", "a/y/1", "b/x/1"].iter().enumerate() {
+        lru.set(key.to_string(), i as u64).unwrap();
+    }
+    assert_eq!(lru.invalidate_prefix("a/x/"), 2);
+    assert_eq!(lru.get(&"a/x/1".to_string()).unwrap(), None);
+    assert_eq!(lru.get(&"a/x/2".to_string()).unwrap(), None);
+    assert_eq!(lru.get(&"a/y/1".to_string()).unwrap(), Some(2));
+    assert_eq!(lru.len(), 2);
+
+    // keys set afresh are indexed again.
+    lru.set("a/x/1".to_string(), 5).unwrap();
+    assert_eq!(lru.invalidate_prefix("a/"), 2);
+    assert_eq!(lru.invalidate_prefix("a/"), 0);
+    assert_eq!(recency(&lru), vec!["b/x/1".to_string()]);
+
+    // without the index, matching entries are invalidated lazily.
+    let mut lru: Lru<String, u64> = LruBuilder::default().build_default();
+    lru.set("a/x/1".to_string(), 0).unwrap();
+    lru.set("b/x/1".to_string(), 1).unwrap();
+    assert_eq!(lru.invalidate_prefix("a/"), 0);
+    assert_eq!(lru.get(&"a/x/1".to_string()).unwrap(), None);
+    assert_eq!(lru.get(&"b/x/1".to_string()).unwrap(), Some(1));
+}
+
+#[test]
+fn test_shard_fold() {
+    type ShardedMap = crate::Sharded<u64, Value<u64, u64>, DefaultHasher>;
+    type TieredMap = crate::Tiered<u64, Value<u64, u64>, DefaultHasher>;
+
+    for shard_fold in [Fold::Mix, Fold::Xor, Fold::High].iter().cloned() {
+        let builder = LruBuilder { shard_fold, ..LruBuilder::default() };
+        let mut lru: Lru<u64, u64, DefaultHasher, ShardedMap> =
+            builder.clone().build_backend(DefaultHasher::default());
+        let mut tiered: Lru<u64, u64, DefaultHasher, TieredMap> =
+            builder.build_backend(DefaultHasher::default());
+        for key in 0..1000 {
+            lru.set(key, key).unwrap();
+            tiered.set(key, key).unwrap();
+        }
+        for key in 0..1000 {
+            assert_eq!(lru.get(&key).unwrap(), Some(key), "{:?}", shard_fold);
+            assert_eq!(tiered.get(&key).unwrap(), Some(key), "{:?}", shard_fold);
+        }
+    }
+}
+
+#[test]
+fn test_swap_concurrent() {
+    let lru: Lru<u64, u64> = {
+        let builder = LruBuilder { max_threads: 2, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    let lru = Arc::new(lru);
+
+    // more swapping threads than max_threads, all through the same handle.
+    let handles: Vec<_> = (0..8)
+        .map(|id| {
+            let lru = Arc::clone(&lru);
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    lru.swap(i % 64, id).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles.into_iter() {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(lru.len(), 64);
+    assert_eq!(lru.stats().n_sets, 8000);
+    for key in 0..64 {
+        assert!(lru.get(&key).unwrap().unwrap() < 8);
+    }
+}
+
+#[test]
+fn test_set_if_version() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    assert_eq!(lru.get_versioned(&1).unwrap(), None);
+    match lru.set_if_version(1, 10, 0) {
+        Err(Error::VersionMismatch(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get(&1).unwrap(), None);
+
+    lru.set(1, 10).unwrap();
+    let (value, version) = lru.get_versioned(&1).unwrap().unwrap();
+    assert_eq!(value, 10);
+    assert_eq!(lru.set_if_version(1, 11, version).unwrap(), 10);
+    let (value, ver) = lru.get_versioned(&1).unwrap().unwrap();
+    assert_eq!(value, 11);
+    assert!(ver > version, "{} {}", ver, version);
+
+    // stale version fails, leaving the entry as is.
+    match lru.set_if_version(1, 12, version) {
+        Err(Error::VersionMismatch(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get_versioned(&1).unwrap(), Some((11, ver)));
+
+    lru.set(1, 13).unwrap();
+    match lru.set_if_version(1, 14, ver) {
+        Err(Error::VersionMismatch(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get(&1).unwrap(), Some(13));
+    assert_eq!(lru.len(), 1);
+}
+
+#[test]
+fn test_increment() {
+    let mut lru: Lru<u64, i64> = LruBuilder::default().build_default();
+    assert_eq!(lru.increment(1, 5).unwrap(), 5);
+    assert_eq!(lru.increment(1, 3).unwrap(), 8);
+    assert_eq!(lru.decrement(1, 10).unwrap(), -2);
+    assert_eq!(lru.decrement(2, 1).unwrap(), -1);
+    assert_eq!(lru.get(&1).unwrap(), Some(-2));
+
+    lru.set(3, i64::MAX).unwrap();
+    match lru.increment(3, 1) {
+        Err(Error::Overflow(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    match lru.decrement(3, i64::MIN) {
+        Err(Error::Overflow(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get(&3).unwrap(), Some(i64::MAX));
+
+    // value shall fit in V, else the entry is left as is.
+    let mut lru: Lru<u64, u8> = LruBuilder::default().build_default();
+    assert_eq!(lru.increment(1, 255).unwrap(), 255);
+    match lru.increment(1, 1) {
+        Err(Error::Overflow(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    match lru.decrement(2, 1) {
+        Err(Error::Overflow(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get(&1).unwrap(), Some(255));
+    assert_eq!(lru.get(&2).unwrap(), None);
+}
+
+#[test]
+fn test_merge() {
+    let mut lru: Lru<u64, String> = LruBuilder::default().build_default();
+    let combine = |old: String, value: String| old + "," + &value;
+    lru.merge(1, "a".to_string(), combine).unwrap();
+    assert_eq!(lru.get(&1).unwrap(), Some("a".to_string()));
+    lru.merge(1, "b".to_string(), combine).unwrap();
+    lru.merge(1, "c".to_string(), combine).unwrap();
+    assert_eq!(lru.get(&1).unwrap(), Some("a,b,c".to_string()));
+
+    // merges from all handles are applied one after the other.
+    let handles: Vec<_> = (0..4)
+        .map(|id| {
+            let mut lru = lru.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    let combine = |old: String, value: String| old + "," + &value;
+                    lru.merge(2, format!("{}", id * 100 + i), combine).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles.into_iter() {
+        handle.join().unwrap();
+    }
+    let value = lru.get(&2).unwrap().unwrap();
+    let mut items: Vec<u64> = value.split(',').map(|x| x.parse().unwrap()).collect();
+    items.sort_unstable();
+    assert_eq!(items, (0..400).collect::<Vec<u64>>());
+    assert_eq!(lru.len(), 2);
+}
+
+#[test]
+fn test_remove_if() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..4 {
+        lru.set(key, key * 10).unwrap();
+    }
+    assert_eq!(lru.remove_if(&9, |_| true).unwrap(), None);
+    assert_eq!(lru.remove_if(&1, |value| *value > 10).unwrap(), None);
+    assert_eq!(lru.get(&1).unwrap(), Some(10));
+    assert_eq!(lru.remove_if(&1, |value| *value == 10).unwrap(), Some(10));
+    assert_eq!(lru.get(&1).unwrap(), None);
+    assert_eq!(lru.len(), 3);
+
+    // invalidated entries are already gone.
+    lru.invalidate_entries_if(|key, _| *key == 2);
+    assert_eq!(lru.remove_if(&2, |_| true).unwrap(), None);
+    assert_eq!(recency(&lru), vec![0, 3]);
+}
+
+#[test]
+fn test_take() {
+    let mut lru: Lru<u64, String> = LruBuilder::default().build_default();
+    lru.set(1, "one".to_string()).unwrap();
+    lru.set(2, "two".to_string()).unwrap();
+    lru.on_expire(1, |_, _| ());
+
+    assert_eq!(lru.take(&1).unwrap(), Some("one".to_string()));
+    assert_eq!(lru.take(&1).unwrap(), None);
+    assert_eq!(lru.get(&1).unwrap(), None);
+    assert_eq!(lru.len(), 1);
+    assert_eq!(recency(&lru), vec![2]);
+    // callbacks stay registered.
+    assert_eq!(lru.cancel_on_expire(&1), 1);
+}
+
+#[test]
+fn test_scan() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    let (entries, cursor) = lru.scan(Cursor::default(), 8);
+    assert!(entries.is_empty());
+    assert!(cursor.is_done());
+
+    for key in 0..100 {
+        lru.set(key, key * 10).unwrap();
+    }
+    let n_gets = lru.stats().n_gets;
+    let (mut cursor, mut seen) = (Cursor::default(), vec![]);
+    while !cursor.is_done() {
+        let (entries, next) = lru.scan(cursor, 7);
+        assert!(entries.len() <= 7, "{}", entries.len());
+        seen.extend(entries);
+        cursor = next;
+    }
+    seen.sort_unstable();
+    let entries: Vec<(u64, u64)> = (0..100).map(|key| (key, key * 10)).collect();
+    assert_eq!(seen, entries);
+    // entries are read without recording an access.
+    assert_eq!(lru.stats().n_gets, n_gets);
+}
+
+#[test]
+fn test_wait_for() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    lru.set(1, 10).unwrap();
+    assert_eq!(lru.wait_for(&1, Duration::from_secs(5)).unwrap(), Some(10));
+
+    let start = Instant::now();
+    assert_eq!(lru.wait_for(&2, Duration::from_millis(50)).unwrap(), None);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let mut other = lru.clone();
+    let setter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        other.set(2, 20).unwrap()
+    });
+    assert_eq!(lru.wait_for(&2, Duration::from_secs(5)).unwrap(), Some(20));
+    assert_eq!(setter.join().unwrap(), None);
+}
+
+#[test]
+fn test_lock_key() {
+    use std::time::{Duration, Instant};
+
+    let lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    let guard = lru.lock_key(1);
+    assert!(lru.try_lock_key(1).is_none());
+    assert!(lru.try_lock_key(2).is_some());
+
+    let other = lru.clone();
+    let locker = thread::spawn(move || {
+        let _guard = other.lock_key(1);
+        Instant::now()
+    });
+    thread::sleep(Duration::from_millis(50));
+    let released = Instant::now();
+    mem::drop(guard);
+    assert!(locker.join().unwrap() >= released);
+
+    // key is free once the guard is dropped, even though it is not cached.
+    assert!(lru.try_lock_key(1).is_some());
+    assert_eq!(lru.len(), 0);
+}
+
+#[test]
+fn test_on_expire() {
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let max_old = Some(Duration::from_millis(50));
+        LruBuilder { max_old, ..LruBuilder::default() }.build_default()
+    };
+    let (tx, rx) = mpsc::channel();
+    for key in 0..3 {
+        let tx = tx.clone();
+        lru.on_expire(key, move |key, value| tx.send((*key, *value)).unwrap());
+    }
+    assert_eq!(lru.cancel_on_expire(&2), 1);
+    for key in 0..3 {
+        lru.set(key, key * 10).unwrap();
+    }
+    // evictor leaves the few most recent access nodes alone.
+    for key in 10..20 {
+        lru.set(key, key * 10).unwrap();
+    }
+    // explicit removal does not fire the callback.
+    lru.take(&1).unwrap();
+
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), (0, 0));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 6 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(lru.len() <= 6, "{:?}", recency(&lru));
+    assert!(rx.try_recv().is_err());
+    assert_eq!(lru.stats().n_expire_callbacks, 1);
+    assert_eq!(lru.cancel_on_expire(&0), 0);
+    assert_eq!(lru.cancel_on_expire(&1), 1);
+}
+
+#[test]
+fn test_on_stale() {
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = LruBuilder {
+        soft_ttl: Some(Duration::from_millis(50)),
+        stale_grace: Duration::from_secs(60),
+        ..LruBuilder::default()
+    }
+    .build_default();
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    lru.on_stale(move |key, value| {
+        if *key < 10 {
+            tx.lock().unwrap().send((*key, *value)).unwrap()
+        }
+    });
+
+    lru.set(1, 10).unwrap();
+    assert!(!lru.get_entry(&1).unwrap().unwrap().is_stale());
+    // evictor leaves the few most recent access nodes alone.
+    for key in 10..20 {
+        lru.set(key, key).unwrap();
+    }
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), (1, 10));
+
+    // stale entries are kept through the grace, and flagged only once.
+    thread::sleep(Duration::from_millis(50));
+    assert!(rx.try_recv().is_err());
+    let entry = lru.get_entry(&1).unwrap().unwrap();
+    assert_eq!(*entry.value(), 10);
+    assert!(entry.is_stale());
+    assert!(lru.stats().n_stale >= 1);
+}
+
+#[test]
+fn test_time_to_idle() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let time_to_idle = Some(Duration::from_millis(200));
+        LruBuilder { time_to_idle, ..LruBuilder::default() }.build_default()
+    };
+    lru.set(1, 10).unwrap();
+    lru.set(2, 20).unwrap();
+
+    // reads keep the entry alive, past its idle time.
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(5);
+    while (lru.len() > 1 || start.elapsed() < Duration::from_millis(400))
+        && Instant::now() < deadline
+    {
+        assert_eq!(lru.get(&1).unwrap(), Some(10));
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(lru.len(), 1);
+    assert_eq!(lru.get(&1).unwrap(), Some(10));
+    assert_eq!(lru.get(&2).unwrap(), None);
+}
+
+#[test]
+fn test_access_sample() {
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder { access_sample: 4, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    lru.set(1, 10).unwrap();
+    lru.set(2, 20).unwrap();
+    for _ in 0..8 {
+        assert_eq!(lru.get(&1).unwrap(), Some(10));
+    }
+    // one in every four gets is recorded.
+    assert_eq!(lru.stats().n_swaps, 2);
+    assert_eq!(recency(&lru), vec![2, 1]);
+    let n = lru.map.get_with(&1, |v| v.n_access.load(SeqCst)).unwrap();
+    assert_eq!(n, 8);
+}
+
+#[test]
+fn test_gdsf_evicts_large() {
+    let builder = LruBuilder {
+        max_memory: Some(400),
+        policy: Policy::Gdsf,
+        ..LruBuilder::default()
+    };
+    let weigher = |_: &u64, value: &Vec<u8>| value.len();
+    let mut lru: Lru<u64, Vec<u8>> =
+        builder.build_weighed(DefaultHasher::default(), weigher);
+    lru.pause_eviction();
+    for key in 1..=20 {
+        lru.set(key, vec![0; 10]).unwrap();
+    }
+    // recent, but large and never read.
+    lru.set(0, vec![0; 300]).unwrap();
+    for key in 21..=26 {
+        lru.set(key, vec![0; 10]).unwrap();
+    }
+    for key in 1..=26 {
+        lru.get(&key).unwrap();
+    }
+
+    lru.resume_eviction().unwrap();
+    assert_eq!(lru.cur_memory.load(SeqCst), 260);
+    assert_eq!(lru.get(&0).unwrap(), None);
+    for key in 1..=26 {
+        assert!(lru.get(&key).unwrap().is_some(), "{}", key);
+    }
+}
+
+#[test]
+fn test_clock_pro_keeps_referenced() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder {
+            max_entries: 16,
+            policy: Policy::ClockPro,
+            ..LruBuilder::default()
+        };
+        builder.build_default()
+    };
+    for key in 0..16 {
+        lru.set(key, key).unwrap();
+    }
+    for key in (16..48).step_by(8) {
+        for key in 0..4 {
+            lru.get(&key).unwrap();
+        }
+        for key in key..(key + 8) {
+            lru.set(key, key).unwrap();
+        }
+    }
+    // gets only set a reference bit.
+    assert_eq!(lru.stats().n_swaps, 0);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 24 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(lru.len() <= 24, "{}", lru.len());
+    for key in 0..4 {
+        assert_eq!(lru.get(&key).unwrap(), Some(key));
+    }
+}
+
+#[test]
+fn test_priority() {
+    let mut lru: Lru<u64, u64> = {
+        let builder = LruBuilder { max_entries: 16, ..LruBuilder::default() };
+        builder.build_default()
+    };
+    lru.pause_eviction();
+    for key in 0..4 {
+        lru.set_with_priority(key, key, Priority::High).unwrap();
+    }
+    for key in 4..8 {
+        lru.set_with_priority(key, key, Priority::Low).unwrap();
+    }
+    for key in 8..40 {
+        lru.set(key, key).unwrap();
+    }
+
+    lru.resume_eviction().unwrap();
+    assert_eq!(lru.len(), 16);
+    assert_eq!(lru.stats().n_prioritized, 8);
+    let keys = recency(&lru);
+    assert!((0..4).all(|key| keys.contains(&key)), "{:?}", keys);
+    assert!((4..8).all(|key| !keys.contains(&key)), "{:?}", keys);
+}
+
+#[test]
+fn test_watermarks() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = {
+        let watermarks = Some((1.0, 0.5));
+        LruBuilder {
+            max_entries: 16,
+            watermarks,
+            ..LruBuilder::default()
+        }
+        .build_default()
+    };
+    lru.pause_eviction();
+    for key in 0..20 {
+        lru.set(key, key).unwrap();
+    }
+    // crossing the high mark evicts down to the low mark.
+    lru.resume_eviction().unwrap();
+    assert_eq!(lru.len(), 8);
+    assert_eq!(recency(&lru), (12..20).collect::<Vec<u64>>());
+
+    // and nothing is evicted until the high mark is crossed again.
+    for key in 20..28 {
+        lru.set(key, key).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(lru.len(), 16);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_export_import_jsonl() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, String> = LruBuilder::default().build_default();
+    for key in 0..3 {
+        lru.set(key, format!("v{}", key)).unwrap();
+    }
+    lru.get(&1).unwrap();
+    lru.get(&1).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let mut data = vec![];
+    assert_eq!(lru.export_jsonl(&mut data).unwrap(), 3);
+    let text = String::from_utf8(data.clone()).unwrap();
+    assert_eq!(text.lines().count(), 3);
+    // least recently used first.
+    assert!(text.starts_with(r#"{"key":0,"value":"v0""#), "{}", text);
+
+    let mut copy: Lru<u64, String> = LruBuilder::default().build_default();
+    assert_eq!(copy.import_jsonl(data.as_slice()).unwrap(), 3);
+    assert_eq!(recency(&copy), vec![0, 2, 1]);
+    let hits = |lru: &Lru<u64, String>| lru.map.get_with(&1, |v| v.n_access.load(SeqCst));
+    assert_eq!(hits(&copy), hits(&lru));
+    let entry = copy.get_entry(&1).unwrap().unwrap();
+    assert_eq!(entry.value(), "v1");
+    assert!(entry.age() >= Duration::from_millis(50), "{:?}", entry.age());
+
+    match copy.import_jsonl("{\"key\":".as_bytes()) {
+        Err(Error::FailConvert(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(copy.len(), 3);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_save_load() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..3 {
+        lru.set(key, key * 10).unwrap();
+    }
+    let mut data = vec![];
+    assert_eq!(lru.save(&mut data, &Json).unwrap(), 3);
+
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    let report = copy.load(data.as_slice(), &Json).unwrap();
+    assert_eq!((report.n_loaded, report.n_corrupt, report.n_rejected), (3, 0, 0));
+    assert!(report.complete);
+    assert_eq!(recency(&copy), vec![0, 1, 2]);
+    assert_eq!(copy.get(&2).unwrap(), Some(20));
+
+    // truncated snapshot is loaded up to its last whole record.
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    let report = copy.load(&data[..data.len() - 4], &Json).unwrap();
+    assert_eq!((report.n_loaded, report.n_corrupt), (3, 0));
+    assert!(!report.complete);
+
+    // records failing their checksum are skipped, first record follows the
+    // 8-byte header and its 8-byte frame.
+    let mut corrupt = data.clone();
+    corrupt[16] ^= 0xFF;
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    let report = copy.load(corrupt.as_slice(), &Json).unwrap();
+    assert_eq!((report.n_loaded, report.n_corrupt), (2, 1));
+    assert!(!report.complete);
+    assert_eq!(copy.get(&0).unwrap(), None);
+
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    match copy.load(&data[4..], &Json) {
+        Err(Error::FailConvert(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[cfg(feature = "encrypt")]
+#[test]
+fn test_save_encrypted() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..3 {
+        lru.set(key, key * 10).unwrap();
+    }
+    let codec = crate::Encrypted::new(Json, &[7; 32]);
+    let mut data = vec![];
+    assert_eq!(lru.save(&mut data, &codec).unwrap(), 3);
+    // records are sealed.
+    let mut plain = vec![];
+    lru.save(&mut plain, &Json).unwrap();
+    assert!(data.windows(7).all(|w| w != b"\"value\""));
+    assert!(plain.windows(7).any(|w| w == b"\"value\""));
+
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    let report = copy.load(data.as_slice(), &codec).unwrap();
+    assert_eq!((report.n_loaded, report.n_corrupt), (3, 0));
+    assert_eq!(copy.get(&1).unwrap(), Some(10));
+
+    // records fail to authenticate with another key.
+    let other = crate::Encrypted::new(Json, &[8; 32]);
+    let mut copy: Lru<u64, u64> = LruBuilder::default().build_default();
+    let report = copy.load(data.as_slice(), &other).unwrap();
+    assert_eq!((report.n_loaded, report.n_corrupt), (0, 3));
+    assert!(copy.is_empty());
+}
+
+#[test]
+fn test_get_many_or_load() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    lru.set(1, 10).unwrap();
+
+    let mut n_loads = 0;
+    let values = lru
+        .get_many_or_load(&[1, 2, 3, 2], |keys| {
+            n_loads += 1;
+            assert!(!keys.contains(&1), "{:?}", keys);
+            Ok(vec![(2, 20)])
+        })
+        .unwrap();
+    assert_eq!(values, vec![Some(10), Some(20), None, Some(20)]);
+    assert_eq!(n_loads, 1);
+    assert_eq!(lru.get(&2).unwrap(), Some(20));
+    assert_eq!(lru.get(&3).unwrap(), None);
+
+    // loader is not called when all keys are cached.
+    let values = lru.get_many_or_load(&[2, 1], |_| panic!("unexpected load")).unwrap();
+    assert_eq!(values, vec![Some(20), Some(10)]);
+
+    // loader errors are returned as is.
+    match lru.get_many_or_load(&[4], |_| err_at!(IOError, msg: "load")) {
+        Err(Error::IOError(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+
+    // while frozen, loaded values are returned without inserting them.
+    lru.freeze();
+    let values = lru.get_many_or_load(&[5], |_| Ok(vec![(5, 50)])).unwrap();
+    assert_eq!(values, vec![Some(50)]);
+    lru.unfreeze();
+    assert_eq!(lru.get(&5).unwrap(), None);
+    assert_eq!(lru.len(), 2);
+}
+
+#[test]
+fn test_peek_lru() {
+    use std::time::Duration;
+
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    assert!(lru.peek_lru().unwrap().is_none());
+
+    for key in 0..10 {
+        lru.set(key, key * 10).unwrap();
+    }
+    lru.get(&0).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    let n_gets = lru.stats().n_gets;
+    let keys = recency(&lru);
+    let (key, value, age) = lru.peek_lru().unwrap().unwrap();
+    assert_eq!((key, value), (1, 10));
+    assert!(age >= Duration::from_millis(20), "{:?}", age);
+    // peek does not record an access.
+    assert_eq!(lru.peek_lru().unwrap().unwrap().0, 1);
+    assert_eq!(recency(&lru), keys);
+    assert_eq!(lru.stats().n_gets, n_gets);
+}
+
+#[test]
+fn test_compact() {
+    let mut lru: Lru<u64, u64> = LruBuilder::default().build_default();
+    for key in 0..32 {
+        lru.set(key, key).unwrap();
+    }
+    for key in 0..16 {
+        lru.get(&key).unwrap();
+    }
+    let keys = recency(&lru);
+
+    assert_eq!(lru.compact(0), 0);
+    assert!(lru.compact(usize::MAX) <= 16);
+    assert_eq!(lru.health().n_tombstones, 0);
+    assert_eq!(recency(&lru), keys);
+    assert_eq!(lru.len(), 32);
+}
+
+#[test]
+fn test_max_entry_weight() {
+    let builder = LruBuilder { max_entry_weight: Some(8), ..LruBuilder::default() };
+    let weigher = |_: &u64, value: &Vec<u8>| value.len();
+    let mut lru: Lru<u64, Vec<u8>> =
+        builder.build_weighed(DefaultHasher::default(), weigher);
+
+    lru.set(1, vec![1; 8]).unwrap();
+    match lru.set(1, vec![2; 9]) {
+        Err(Error::Oversized(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    match lru.set(2, vec![2; 9]) {
+        Err(Error::Oversized(_, _)) => (),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(lru.get(&1).unwrap(), Some(vec![1; 8]));
+    assert_eq!(lru.get(&2).unwrap(), None);
+    assert_eq!(lru.stats().n_oversized, 2);
+    assert_eq!(lru.len(), 1);
+}
+
+#[test]
+fn test_protect_young() {
+    use std::time::{Duration, Instant};
+
+    let mut lru: Lru<u64, u64> = {
+        let protect_young = Some(Duration::from_secs(1));
+        LruBuilder {
+            max_entries: 8,
+            protect_young,
+            ..LruBuilder::default()
+        }
+        .build_default()
+    };
+    for key in 0..32 {
+        lru.set(key, key).unwrap();
+    }
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(lru.len(), 32);
+
+    // evicted for capacity once they are no longer young.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while lru.len() > 8 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(lru.len(), 8);
+    assert_eq!(recency(&lru), (24..32).collect::<Vec<u64>>());
+}
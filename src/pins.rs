@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::{Arc, Mutex};
+
+/// Keys pinned via `Lru::pin`, protected from eviction for capacity, memory
+/// and expiry. Pinned keys are still removed when invalidated.
+///
+/// Pins are counted, a key pinned twice stays pinned until unpinned twice.
+/// Pins are indexed by key's hash, computed by the caller.
+pub(crate) struct Pins<K> {
+    pins: Mutex<HashMap<u64, Vec<(K, usize)>>>,
+    n_pins: AtomicUsize,
+}
+
+impl<K> Default for Pins<K> {
+    fn default() -> Pins<K> {
+        Pins {
+            pins: Mutex::new(HashMap::new()),
+            n_pins: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<K> Pins<K>
+where
+    K: PartialEq,
+{
+    pub fn pin(&self, hash: u64, key: K) {
+        let mut pins = self.pins.lock().unwrap();
+        let entries = pins.entry(hash).or_default();
+        match entries.iter_mut().find(|(k, _)| k == &key) {
+            Some((_, n)) => *n += 1,
+            None => entries.push((key, 1)),
+        }
+        self.n_pins.fetch_add(1, SeqCst);
+    }
+
+    /// Drop one pin on `key`, return false if `key` is not pinned.
+    pub fn unpin(&self, hash: u64, key: &K) -> bool {
+        let mut pins = self.pins.lock().unwrap();
+        let entries = match pins.get_mut(&hash) {
+            Some(entries) => entries,
+            None => return false,
+        };
+        let off = match entries.iter().position(|(k, _)| k == key) {
+            Some(off) => off,
+            None => return false,
+        };
+        entries[off].1 -= 1;
+        if entries[off].1 == 0 {
+            entries.remove(off);
+        }
+        if entries.is_empty() {
+            pins.remove(&hash);
+        }
+        self.n_pins.fetch_sub(1, SeqCst);
+        true
+    }
+
+    /// Return whether no key is pinned, callers can skip hashing keys then.
+    pub fn is_empty(&self) -> bool {
+        self.n_pins.load(SeqCst) == 0
+    }
+
+    pub fn is_pinned(&self, hash: u64, key: &K) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let pins = self.pins.lock().unwrap();
+        match pins.get(&hash) {
+            Some(entries) => entries.iter().any(|(k, _)| k == key),
+            None => false,
+        }
+    }
+}
+
+/// Guard returned by [crate::Lru::pin_guard], the key stays pinned for as long
+/// as the guard is alive, and is unpinned when the guard is dropped, including
+/// while unwinding from a panic.
+pub struct PinGuard<K>
+where
+    K: PartialEq,
+{
+    pins: Arc<Pins<K>>,
+    hash: u64,
+    key: K,
+}
+
+impl<K> PinGuard<K>
+where
+    K: PartialEq,
+{
+    pub(crate) fn new(pins: Arc<Pins<K>>, hash: u64, key: K) -> PinGuard<K>
+    where
+        K: Clone,
+    {
+        pins.pin(hash, key.clone());
+        PinGuard { pins, hash, key }
+    }
+
+    /// Return the pinned key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K> Drop for PinGuard<K>
+where
+    K: PartialEq,
+{
+    fn drop(&mut self) {
+        self.pins.unpin(self.hash, &self.key);
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Mutex;
+
+/// Return the bytes of a key, refer `LruBuilder::prefix_index`.
+pub type KeyBytes<K> = fn(&K) -> &[u8];
+
+pub(crate) fn key_bytes<K: AsRef<[u8]>>(key: &K) -> &[u8] {
+    key.as_ref()
+}
+
+/// Index of keys by their bytes, refer `Lru::invalidate_prefix`.
+///
+/// Keys are spread across shards by their hash, and each shard holds its
+/// keys ordered by their bytes, so that keys sharing a prefix are found with
+/// a range lookup on every shard. Keys of evicted entries are not removed
+/// from the index, they are pruned as the shard grows.
+pub(crate) struct PrefixIndex<K> {
+    shards: Vec<Mutex<Shard<K>>>,
+    to_bytes: KeyBytes<K>,
+}
+
+struct Shard<K> {
+    keys: BTreeMap<Vec<u8>, K>,
+    prune_at: usize,
+}
+
+impl<K> PrefixIndex<K> {
+    pub fn new(n_shards: usize, to_bytes: KeyBytes<K>) -> PrefixIndex<K> {
+        let shards = (0..std::cmp::max(n_shards, 1))
+            .map(|_| Mutex::new(Shard { keys: BTreeMap::new(), prune_at: 16 }))
+            .collect();
+        PrefixIndex { shards, to_bytes }
+    }
+
+    /// Index `key`, whose hash is `hash`, `is_live` is used to prune keys of
+    /// evicted entries.
+    pub fn add<F>(&self, hash: u64, key: &K, is_live: F)
+    where
+        K: Clone,
+        F: Fn(&K) -> bool,
+    {
+        let mut shard = self.to_shard(hash).lock().unwrap();
+        let bytes = (self.to_bytes)(key);
+        if shard.keys.contains_key(bytes) {
+            return;
+        }
+
+        shard.keys.insert(bytes.to_vec(), key.clone());
+        if shard.keys.len() >= shard.prune_at {
+            shard.keys.retain(|_, key| is_live(key));
+            shard.prune_at = std::cmp::max(shard.keys.len() * 2, 16);
+        }
+    }
+
+    /// Remove `key`, whose hash is `hash`, from the index.
+    pub fn remove(&self, hash: u64, key: &K) {
+        let mut shard = self.to_shard(hash).lock().unwrap();
+        shard.keys.remove((self.to_bytes)(key));
+    }
+
+    /// Return indexed keys that start with `prefix`.
+    pub fn to_keys(&self, prefix: &[u8]) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let mut keys = vec![];
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            let range = (Bound::Included(prefix), Bound::Unbounded);
+            let iter = shard.keys.range::<[u8], _>(range);
+            keys.extend(
+                iter.take_while(|(bytes, _)| bytes.starts_with(prefix))
+                    .map(|(_, key)| key.clone()),
+            );
+        }
+        keys
+    }
+
+    fn to_shard(&self, hash: u64) -> &Mutex<Shard<K>> {
+        &self.shards[(hash % (self.shards.len() as u64)) as usize]
+    }
+}
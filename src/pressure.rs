@@ -0,0 +1,138 @@
+//! Module implement host memory-pressure detection and memory limits.
+//!
+//! Evictor polls the configured [Pressure] watcher and, while the host is under
+//! memory pressure, evicts entries down to `pressure_floor` instead of waiting
+//! for the OOM killer to decide.
+//!
+//! [memory_limit] is used to derive a default `max_memory`, refer
+//! `LruBuilder::memory_fraction`.
+
+#[cfg(target_os = "linux")]
+use std::{fs, path, sync::Mutex};
+
+/// Trait to detect memory pressure on the host, implement this for platforms
+/// other than Linux.
+pub trait Pressure: Send + Sync {
+    /// Return whether the host is under memory pressure, called periodically
+    /// from the evictor thread.
+    fn is_under_pressure(&self) -> bool;
+}
+
+/// Memory pressure watcher for Linux, using cgroup v2's `memory.events` and
+/// falling back to pressure-stall-information from `/proc/pressure/memory`.
+///
+/// Pressure is reported when `high` or `max` events were raised for the cgroup
+/// since the last poll, or when `some avg10` stall exceeds `psi_threshold`.
+#[cfg(target_os = "linux")]
+pub struct CgroupPressure {
+    events: path::PathBuf,
+    psi: path::PathBuf,
+    psi_threshold: f64,
+    last: Mutex<(u64, u64)>, // (high, max) event counts at last poll.
+}
+
+#[cfg(target_os = "linux")]
+impl Default for CgroupPressure {
+    fn default() -> CgroupPressure {
+        CgroupPressure::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl CgroupPressure {
+    /// Create a watcher for this process's cgroup. Event counts raised before
+    /// this call are not reported as pressure.
+    pub fn new() -> CgroupPressure {
+        let events = path::PathBuf::from("/sys/fs/cgroup/memory.events");
+        let psi = path::PathBuf::from("/proc/pressure/memory");
+        CgroupPressure::from_files(events, psi)
+    }
+
+    pub(crate) fn from_files(events: path::PathBuf, psi: path::PathBuf) -> Self {
+        let mut val = CgroupPressure {
+            events,
+            psi,
+            psi_threshold: 10.0,
+            last: Mutex::new((0, 0)),
+        };
+        // seed with counts raised so far, else the first poll reports them.
+        if let Some(counts) = val.read_events() {
+            val.last = Mutex::new(counts);
+        }
+        val
+    }
+
+    /// Report pressure when more than `psi_threshold` percent of the last ten
+    /// seconds was spent stalled on memory, default is 10.0.
+    pub fn set_psi_threshold(&mut self, psi_threshold: f64) -> &mut Self {
+        self.psi_threshold = psi_threshold;
+        self
+    }
+
+    fn read_events(&self) -> Option<(u64, u64)> {
+        let text = fs::read_to_string(&self.events).ok()?;
+        let (mut high, mut max) = (0, 0);
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next().and_then(|n| n.parse().ok())) {
+                (Some("high"), Some(n)) => high = n,
+                (Some("max"), Some(n)) => max = n,
+                _ => (),
+            }
+        }
+        Some((high, max))
+    }
+
+    fn read_psi(&self) -> Option<f64> {
+        let text = fs::read_to_string(&self.psi).ok()?;
+        let line = text.lines().find(|line| line.starts_with("some"))?;
+        let avg10 = line.split_whitespace().find(|f| f.starts_with("avg10="))?;
+        avg10.trim_start_matches("avg10=").parse().ok()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Pressure for CgroupPressure {
+    fn is_under_pressure(&self) -> bool {
+        if let Some((high, max)) = self.read_events() {
+            let mut last = self.last.lock().unwrap();
+            let raised = high > last.0 || max > last.1;
+            *last = (high, max);
+            if raised {
+                return true;
+            }
+        }
+
+        match self.read_psi() {
+            Some(avg10) => avg10 > self.psi_threshold,
+            None => false,
+        }
+    }
+}
+
+/// Return the memory available to this process, in bytes. That is, the
+/// container's limit from cgroup v2 `memory.max` or cgroup v1
+/// `memory.limit_in_bytes`, if set, else total system RAM from `/proc/meminfo`.
+#[cfg(target_os = "linux")]
+pub fn memory_limit() -> Option<usize> {
+    let cgroups =
+        ["/sys/fs/cgroup/memory.max", "/sys/fs/cgroup/memory/memory.limit_in_bytes"];
+    for file in cgroups.iter() {
+        // "max" for v2, and a page-aligned i64::MAX for v1, mean unlimited.
+        match fs::read_to_string(file).map(|s| s.trim().parse::<u64>()) {
+            Ok(Ok(n)) if n < (1 << 60) => return Some(n as usize),
+            _ => (),
+        }
+    }
+
+    let text = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = text.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Memory limits are not detected for this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn memory_limit() -> Option<usize> {
+    None
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Group of keys, assigned by the classifier installed via
+/// [crate::LruBuilder::quota], say a tenant.
+pub type GroupId = u64;
+
+/// Limits on a group of keys, refer [crate::LruBuilder::quota].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QuotaLimit {
+    /// maximum number of entries cached for the group, None for no limit.
+    pub max_entries: Option<usize>,
+    /// maximum memory footprint of the group's entries, None for no limit.
+    pub max_memory: Option<usize>,
+}
+
+type Classifier<K> = Arc<dyn Fn(&K) -> GroupId + Send + Sync>;
+
+/// Per group limits, enforced by the evictor. Groups without a limit are
+/// bound only by the cache-wide limits. Built via [crate::LruBuilder::quota].
+pub struct Quota<K> {
+    classifier: Classifier<K>,
+    limits: HashMap<GroupId, QuotaLimit>,
+}
+
+/// Entries and memory kept per group, so far, in an evictor pass.
+#[derive(Default)]
+pub(crate) struct Tally {
+    groups: HashMap<GroupId, (usize, usize)>,
+}
+
+impl<K> Quota<K> {
+    pub(crate) fn new(
+        classifier: Classifier<K>,
+        limits: HashMap<GroupId, QuotaLimit>,
+    ) -> Self {
+        Quota { classifier, limits }
+    }
+
+    pub(crate) fn classify(&self, key: &K) -> GroupId {
+        (self.classifier)(key)
+    }
+
+    /// Return whether keeping another entry of `weight` would take `group`
+    /// beyond its limits.
+    pub(crate) fn is_over(&self, tally: &Tally, group: GroupId, weight: usize) -> bool {
+        let limit = match self.limits.get(&group) {
+            Some(limit) => limit,
+            None => return false,
+        };
+        let (n, memory) = tally.groups.get(&group).cloned().unwrap_or_default();
+        limit.max_entries.is_some_and(|m| n + 1 > m)
+            || limit.max_memory.is_some_and(|m| memory + weight > m)
+    }
+}
+
+impl Tally {
+    /// Account an entry of `weight` kept for `group`.
+    pub fn keep(&mut self, group: GroupId, weight: usize) {
+        let entry = self.groups.entry(group).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += weight;
+    }
+}
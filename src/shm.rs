@@ -0,0 +1,480 @@
+//! Experimental cache shared by processes on the same host, refer [ShmLru].
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering::SeqCst};
+use std::{fs, mem, path, ptr};
+
+use crate::{Error, Result};
+
+const MAGIC: u64 = 0x434c_5255_5348_4d31; // "CLRUSHM1"
+const INIT: u64 = 1; // header is being initialized, under the lock.
+const HEADER_SIZE: usize = 128;
+const SLOT_SIZE: usize = mem::size_of::<Slot>();
+// slot states.
+const EMPTY: u32 = 0;
+const LIVE: u32 = 1;
+const WRITING: u32 = 2;
+// number of live slots sampled to pick a victim.
+const SAMPLE: usize = 16;
+// lock waiters wake up this often to check whether the owner is alive.
+const LOCK_POLL: libc::timespec = libc::timespec { tv_sec: 0, tv_nsec: 1_000_000 };
+
+#[repr(C)]
+struct Header {
+    magic: AtomicU64,
+    n_slots: AtomicU64,
+    key_size: AtomicU64,
+    value_size: AtomicU64,
+    // futex word, pid of the process holding the lock, 0 if unlocked.
+    lock: AtomicU32,
+    _pad: AtomicU32,
+    // bumped on every access, orders slots by recency.
+    clock: AtomicU64,
+    n_entries: AtomicU64,
+    n_evicted: AtomicU64,
+    // next slot to sample for a victim.
+    hand: AtomicU64,
+}
+
+// followed by `key_size` bytes of key and `value_size` bytes of value.
+#[repr(C)]
+struct Slot {
+    state: AtomicU32,
+    key_len: AtomicU32,
+    value_len: AtomicU32,
+    _pad: AtomicU32,
+    hash: AtomicU64,
+    // clock as of the last access.
+    access: AtomicU64,
+}
+
+/// Experimental cache of byte-string keys and values, held in a memory
+/// mapped file, say under `/dev/shm`, so that processes on the same host
+/// share a single cache instead of each holding a copy.
+///
+/// Cache is a fixed-size open-addressed table of `n_slots`, each holding a
+/// key of up to `key_size` bytes and a value of up to `value_size` bytes,
+/// fixed when the file is created. Up to three-fourth of the slots are
+/// filled, beyond that an approximate least recently used entry, among a
+/// sample of entries, is evicted on set.
+///
+/// Operations are serialized on a lock in the mapped file, held by process
+/// id. A process dying while holding it does not wedge others, the lock is
+/// taken over and the table is rebuilt from the slots that were completely
+/// written. Unlike [crate::Lru], there is no background evictor and no
+/// expiry, and a process id reused by the time its owner is found dead
+/// leaves the lock held.
+pub struct ShmLru {
+    base: *mut u8,
+    len: usize,
+    n_slots: usize,
+    key_size: usize,
+    value_size: usize,
+    stride: usize,
+}
+
+// all access to the mapping goes through atomics, or under the lock.
+unsafe impl Send for ShmLru {}
+unsafe impl Sync for ShmLru {}
+
+impl Drop for ShmLru {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base as *mut libc::c_void, self.len) };
+    }
+}
+
+impl ShmLru {
+    /// Open the cache at `path`, creating it with `n_slots` of `key_size`
+    /// and `value_size` if missing. Fail with `VersionMismatch` if the
+    /// existing cache was created with another geometry.
+    pub fn open<P>(
+        path: P,
+        n_slots: usize,
+        key_size: usize,
+        value_size: usize,
+    ) -> Result<Self>
+    where
+        P: AsRef<path::Path>,
+    {
+        if n_slots < 2 {
+            err_at!(FailConvert, msg: "n_slots {} < 2", n_slots)?;
+        }
+        let stride = match key_size.checked_add(value_size) {
+            Some(n) if n <= usize::MAX - SLOT_SIZE - 7 => (SLOT_SIZE + n + 7) & !7,
+            _ => err_at!(FailConvert, msg: "slot size {}+{}", key_size, value_size)?,
+        };
+        let len = match n_slots.checked_mul(stride) {
+            Some(n) if n <= usize::MAX - HEADER_SIZE => HEADER_SIZE + n,
+            _ => err_at!(FailConvert, msg: "{} slots of {} bytes", n_slots, stride)?,
+        };
+
+        let mut opts = fs::OpenOptions::new();
+        let file = err_at!(IOError, opts.read(true).write(true).create(true).open(path))?;
+        match err_at!(IOError, file.metadata())?.len() as usize {
+            0 => err_at!(IOError, file.set_len(len as u64))?,
+            n if n == len => (),
+            n => err_at!(VersionMismatch, msg: "file size {} != {}", n, len)?,
+        }
+
+        let base = unsafe {
+            use std::os::unix::io::AsRawFd;
+
+            let (prot, flags) = (libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED);
+            libc::mmap(ptr::null_mut(), len, prot, flags, file.as_raw_fd(), 0)
+        };
+        if base == libc::MAP_FAILED {
+            err_at!(IOError, Err(std::io::Error::last_os_error()))?;
+        }
+
+        let val = ShmLru {
+            base: base as *mut u8,
+            len,
+            n_slots,
+            key_size,
+            value_size,
+            stride,
+        };
+        val.init()?;
+        Ok(val)
+    }
+
+    /// Return the value for `key`, marking it as recently used.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock()?;
+        let header = self.header();
+
+        let (off, found) = self.find(to_hash(key), key);
+        if !found {
+            return Ok(None);
+        }
+        let slot = self.slot(off);
+        slot.access.store(header.clock.fetch_add(1, SeqCst) + 1, SeqCst);
+        Ok(Some(self.value(off).to_vec()))
+    }
+
+    /// Set `value` for `key`, return the value it replaces. Fail with
+    /// `Oversized` if either exceeds its configured size.
+    pub fn set(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        if key.len() > self.key_size || value.len() > self.value_size {
+            let (k, v) = (key.len(), value.len());
+            err_at!(Oversized, msg: "key {} value {} exceed slot size", k, v)?;
+        }
+
+        let _guard = self.lock()?;
+        let header = self.header();
+
+        let hash = to_hash(key);
+        let (mut off, found) = self.find(hash, key);
+        let old = match found {
+            true => Some(self.value(off).to_vec()),
+            false if self.len() >= self.max_entries() => {
+                // eviction shifts slots, look up the empty slot again.
+                self.evict();
+                off = self.find(hash, key).0;
+                None
+            }
+            false => None,
+        };
+
+        let slot = self.slot(off);
+        slot.state.store(WRITING, SeqCst);
+        unsafe {
+            ptr::copy_nonoverlapping(key.as_ptr(), self.key_ptr(off), key.len());
+            ptr::copy_nonoverlapping(value.as_ptr(), self.value_ptr(off), value.len());
+        }
+        slot.key_len.store(key.len() as u32, SeqCst);
+        slot.value_len.store(value.len() as u32, SeqCst);
+        slot.hash.store(hash, SeqCst);
+        slot.access.store(header.clock.fetch_add(1, SeqCst) + 1, SeqCst);
+        slot.state.store(LIVE, SeqCst);
+
+        if !found {
+            header.n_entries.fetch_add(1, SeqCst);
+        }
+        Ok(old)
+    }
+
+    /// Remove `key`, return its value.
+    pub fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock()?;
+
+        let (off, found) = self.find(to_hash(key), key);
+        if !found {
+            return Ok(None);
+        }
+        let value = self.value(off).to_vec();
+        self.erase(off);
+        self.header().n_entries.fetch_sub(1, SeqCst);
+        Ok(Some(value))
+    }
+
+    /// Return the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.header().n_entries.load(SeqCst) as usize
+    }
+
+    /// Return whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the maximum number of entries held by the cache.
+    pub fn max_entries(&self) -> usize {
+        self.n_slots - std::cmp::max(self.n_slots / 4, 1)
+    }
+
+    /// Return the number of entries evicted so far, across all processes.
+    pub fn n_evicted(&self) -> usize {
+        self.header().n_evicted.load(SeqCst) as usize
+    }
+}
+
+impl ShmLru {
+    // initialize the header of a freshly created file, under the lock, and
+    // validate the geometry. A creator that died halfway, leaving the header
+    // in INIT, is taken over like any other lock holder.
+    fn init(&self) -> Result<()> {
+        let header = self.header();
+        let geometry = [self.n_slots, self.key_size, self.value_size];
+
+        // don't take the lock, and recover, on a file that isn't a cache.
+        match header.magic.load(SeqCst) {
+            0 | INIT | MAGIC => (),
+            magic => err_at!(VersionMismatch, msg: "magic {:x}", magic)?,
+        }
+
+        let guard = self.lock()?;
+        match header.magic.load(SeqCst) {
+            0 | INIT => {
+                header.magic.store(INIT, SeqCst);
+                header.n_slots.store(self.n_slots as u64, SeqCst);
+                header.key_size.store(self.key_size as u64, SeqCst);
+                header.value_size.store(self.value_size as u64, SeqCst);
+                header.magic.store(MAGIC, SeqCst);
+            }
+            MAGIC => (),
+            magic => err_at!(VersionMismatch, msg: "magic {:x}", magic)?,
+        }
+        mem::drop(guard);
+
+        let found = [
+            header.n_slots.load(SeqCst) as usize,
+            header.key_size.load(SeqCst) as usize,
+            header.value_size.load(SeqCst) as usize,
+        ];
+        match found == geometry {
+            true => Ok(()),
+            false => {
+                err_at!(VersionMismatch, msg: "geometry {:?} != {:?}", found, geometry)
+            }
+        }
+    }
+
+    // take the lock, taking it over from a process that died holding it.
+    fn lock(&self) -> Result<Guard<'_>> {
+        let word = &self.header().lock;
+        let pid = std::process::id();
+        loop {
+            let owner = match word.compare_exchange(0, pid, SeqCst, SeqCst) {
+                Ok(_) => break Ok(Guard { lru: self }),
+                Err(owner) => owner,
+            };
+            if !is_alive(owner)
+                && word.compare_exchange(owner, pid, SeqCst, SeqCst).is_ok()
+            {
+                self.recover();
+                break Ok(Guard { lru: self });
+            }
+            unsafe {
+                let word = word as *const AtomicU32 as *const u32;
+                libc::syscall(libc::SYS_futex, word, libc::FUTEX_WAIT, owner, &LOCK_POLL);
+            }
+        }
+    }
+
+    fn unlock(&self) {
+        let word = &self.header().lock;
+        word.store(0, SeqCst);
+        unsafe {
+            let word = word as *const AtomicU32 as *const u32;
+            libc::syscall(libc::SYS_futex, word, libc::FUTEX_WAKE, 1);
+        }
+    }
+
+    // rebuild the table from completely written slots, after a process died
+    // holding the lock, possibly halfway through a set or a remove.
+    fn recover(&self) {
+        let (mut entries, mut keys) = (vec![], HashSet::new());
+        for off in 0..self.n_slots {
+            let slot = self.slot(off);
+            if slot.state.load(SeqCst) == LIVE && keys.insert(self.key(off).to_vec()) {
+                let (key, value) = (self.key(off).to_vec(), self.value(off).to_vec());
+                entries.push((key, value, slot.access.load(SeqCst)));
+            }
+            slot.state.store(EMPTY, SeqCst);
+        }
+
+        let header = self.header();
+        header.n_entries.store(0, SeqCst);
+        for (key, value, access) in entries.into_iter() {
+            let hash = to_hash(&key);
+            let (off, _) = self.find(hash, &key);
+            let slot = self.slot(off);
+            unsafe {
+                ptr::copy_nonoverlapping(key.as_ptr(), self.key_ptr(off), key.len());
+                ptr::copy_nonoverlapping(
+                    value.as_ptr(),
+                    self.value_ptr(off),
+                    value.len(),
+                );
+            }
+            slot.key_len.store(key.len() as u32, SeqCst);
+            slot.value_len.store(value.len() as u32, SeqCst);
+            slot.hash.store(hash, SeqCst);
+            slot.access.store(access, SeqCst);
+            slot.state.store(LIVE, SeqCst);
+            header.n_entries.fetch_add(1, SeqCst);
+        }
+    }
+
+    // return the slot holding `key`, or else the empty slot to set it in.
+    // Table always has an empty slot, refer max_entries.
+    fn find(&self, hash: u64, key: &[u8]) -> (usize, bool) {
+        let mut off = (hash % (self.n_slots as u64)) as usize;
+        loop {
+            let slot = self.slot(off);
+            match slot.state.load(SeqCst) {
+                EMPTY => break (off, false),
+                _ if slot.hash.load(SeqCst) == hash && self.key(off) == key => {
+                    break (off, true)
+                }
+                _ => off = (off + 1) % self.n_slots,
+            }
+        }
+    }
+
+    // evict the least recently used entry among a sample of entries.
+    fn evict(&self) {
+        let header = self.header();
+        let start = header.hand.load(SeqCst) as usize % self.n_slots;
+
+        let (mut victim, mut n) = (None, 0);
+        for i in 0..self.n_slots {
+            let off = (start + i) % self.n_slots;
+            let slot = self.slot(off);
+            if slot.state.load(SeqCst) != LIVE {
+                continue;
+            }
+            let access = slot.access.load(SeqCst);
+            match victim {
+                Some((_, oldest)) if oldest <= access => (),
+                _ => victim = Some((off, access)),
+            }
+            n += 1;
+            if n == SAMPLE {
+                header.hand.store(((off + 1) % self.n_slots) as u64, SeqCst);
+                break;
+            }
+        }
+
+        if let Some((off, _)) = victim {
+            self.erase(off);
+            header.n_entries.fetch_sub(1, SeqCst);
+            header.n_evicted.fetch_add(1, SeqCst);
+        }
+    }
+
+    // empty slot `off`, shifting back the slots that follow it in its probe
+    // sequence, so that lookups need no tombstones.
+    fn erase(&self, mut off: usize) {
+        self.slot(off).state.store(EMPTY, SeqCst);
+
+        let mut next = off;
+        loop {
+            next = (next + 1) % self.n_slots;
+            let slot = self.slot(next);
+            if slot.state.load(SeqCst) == EMPTY {
+                break;
+            }
+            // slot stays if its home is cyclically within (off, next].
+            let home = (slot.hash.load(SeqCst) % (self.n_slots as u64)) as usize;
+            let stays = match off <= next {
+                true => off < home && home <= next,
+                false => off < home || home <= next,
+            };
+            if !stays {
+                self.move_slot(next, off);
+                off = next;
+            }
+        }
+    }
+
+    // move slot `from` into the empty slot `to`, a process dying halfway
+    // leaves `to` as WRITING, or both live with the same key, refer recover.
+    fn move_slot(&self, from: usize, to: usize) {
+        let (src, dst) = (self.slot(from), self.slot(to));
+        dst.state.store(WRITING, SeqCst);
+        dst.key_len.store(src.key_len.load(SeqCst), SeqCst);
+        dst.value_len.store(src.value_len.load(SeqCst), SeqCst);
+        dst.hash.store(src.hash.load(SeqCst), SeqCst);
+        dst.access.store(src.access.load(SeqCst), SeqCst);
+        unsafe {
+            let n = self.key_size + self.value_size;
+            ptr::copy_nonoverlapping(self.key_ptr(from), self.key_ptr(to), n);
+        }
+        dst.state.store(LIVE, SeqCst);
+        src.state.store(EMPTY, SeqCst);
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.base as *const Header) }
+    }
+
+    fn slot(&self, off: usize) -> &Slot {
+        unsafe { &*(self.base.add(HEADER_SIZE + (off * self.stride)) as *const Slot) }
+    }
+
+    fn key_ptr(&self, off: usize) -> *mut u8 {
+        unsafe { self.base.add(HEADER_SIZE + (off * self.stride) + SLOT_SIZE) }
+    }
+
+    fn value_ptr(&self, off: usize) -> *mut u8 {
+        unsafe { self.key_ptr(off).add(self.key_size) }
+    }
+
+    fn key(&self, off: usize) -> &[u8] {
+        let n = self.slot(off).key_len.load(SeqCst) as usize;
+        unsafe { std::slice::from_raw_parts(self.key_ptr(off), n) }
+    }
+
+    fn value(&self, off: usize) -> &[u8] {
+        let n = self.slot(off).value_len.load(SeqCst) as usize;
+        unsafe { std::slice::from_raw_parts(self.value_ptr(off), n) }
+    }
+}
+
+// holds the lock, released when dropped, including while unwinding.
+struct Guard<'a> {
+    lru: &'a ShmLru,
+}
+
+impl<'a> Drop for Guard<'a> {
+    fn drop(&mut self) {
+        self.lru.unlock()
+    }
+}
+
+// return whether process `pid` is alive.
+fn is_alive(pid: u32) -> bool {
+    match unsafe { libc::kill(pid as libc::pid_t, 0) } {
+        0 => true,
+        _ => std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH),
+    }
+}
+
+// FNV-1a, hashes shall agree across processes, and builds.
+fn to_hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ (*byte as u64)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,221 @@
+//! Module implement the snapshot file format, refer `Lru::save`.
+//!
+//! A snapshot is a header, followed by records, followed by a trailer,
+//! integers are little-endian:
+//!
+//! * header, magic `CLRU` and format version as 4-bytes.
+//! * record, length and crc32 of the encoded record, 4-bytes each, followed
+//!   by the encoded record.
+//! * trailer, `u32::MAX` in place of length, crc32 over all the preceding
+//!   records as 4-bytes, and the number of records as 8-bytes.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{convert::TryFrom, fs, io};
+
+use crate::{export::Record, Error, Result};
+
+const MAGIC: [u8; 4] = *b"CLRU";
+const VERSION: u32 = 1;
+const TRAILER: u32 = u32::MAX;
+// records are never this large, a longer length is from a corrupt file.
+const MAX_RECORD: u32 = 1 << 30;
+
+/// Outcome of [crate::Lru::load].
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    /// number of entries set.
+    pub n_loaded: usize,
+    /// number of entries rejected by the admission callback, or for exceeding
+    /// `max_entry_weight`.
+    pub n_rejected: usize,
+    /// number of records skipped for failing their checksum, or their decode.
+    pub n_corrupt: usize,
+    /// whether the snapshot ended in a trailer matching its records, false if
+    /// it is truncated, say by a torn write, or is corrupt.
+    pub complete: bool,
+}
+
+/// Write records framed as per snapshot format.
+pub(crate) struct Writer<W> {
+    writer: W,
+    hasher: crc32fast::Hasher,
+    n: u64,
+}
+
+impl<W> Writer<W>
+where
+    W: io::Write,
+{
+    pub fn new(mut writer: W) -> Result<Writer<W>> {
+        err_at!(IOError, writer.write_all(&MAGIC))?;
+        err_at!(IOError, writer.write_all(&VERSION.to_le_bytes()))?;
+        Ok(Writer { writer, hasher: crc32fast::Hasher::new(), n: 0 })
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> Result<()> {
+        let len = match u32::try_from(data.len()) {
+            Ok(len) if len < MAX_RECORD => len,
+            _ => return err_at!(Overflow, msg: "record of {} bytes", data.len()),
+        };
+        let crc = crc32fast::hash(data);
+        for buf in [&len.to_le_bytes()[..], &crc.to_le_bytes()[..], data].iter() {
+            self.hasher.update(buf);
+            err_at!(IOError, self.writer.write_all(buf))?;
+        }
+        self.n += 1;
+        Ok(())
+    }
+
+    /// Write the trailer and flush, return the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        let crc = self.hasher.clone().finalize();
+        err_at!(IOError, self.writer.write_all(&TRAILER.to_le_bytes()))?;
+        err_at!(IOError, self.writer.write_all(&crc.to_le_bytes()))?;
+        err_at!(IOError, self.writer.write_all(&self.n.to_le_bytes()))?;
+        err_at!(IOError, self.writer.flush())?;
+        Ok(self.writer)
+    }
+}
+
+/// Frame read from a snapshot.
+pub(crate) enum Frame {
+    /// Encoded record, that matched its checksum.
+    Record(Vec<u8>),
+    /// Record that failed its checksum.
+    Corrupt,
+    /// End of snapshot, true if the trailer matched the records read.
+    End(bool),
+}
+
+/// Read records framed as per snapshot format.
+pub(crate) struct Reader<R> {
+    reader: R,
+    hasher: crc32fast::Hasher,
+    n: u64,
+}
+
+impl<R> Reader<R>
+where
+    R: io::Read,
+{
+    /// Validate the header, fail if `reader` is not a snapshot or is of an
+    /// unknown version.
+    pub fn new(mut reader: R) -> Result<Reader<R>> {
+        let mut header = [0_u8; 8];
+        err_at!(IOError, reader.read_exact(&mut header))?;
+        if header[..4] != MAGIC {
+            return err_at!(FailConvert, msg: "not a snapshot");
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != VERSION {
+            return err_at!(VersionMismatch, msg: "snapshot version {}", version);
+        }
+        Ok(Reader { reader, hasher: crc32fast::Hasher::new(), n: 0 })
+    }
+
+    /// Return the next frame. A truncated snapshot, say from a torn write,
+    /// ends without a matching trailer.
+    pub fn next_frame(&mut self) -> Result<Frame> {
+        let (len, crc) = match self.read_u32s()? {
+            Some((TRAILER, crc)) => {
+                let mut n = [0_u8; 8];
+                let ok = self.read_exact(&mut n)?
+                    && crc == self.hasher.clone().finalize()
+                    && u64::from_le_bytes(n) == self.n;
+                return Ok(Frame::End(ok));
+            }
+            Some((len, _)) if len >= MAX_RECORD => return Ok(Frame::End(false)),
+            Some((len, crc)) => (len, crc),
+            None => return Ok(Frame::End(false)),
+        };
+
+        let mut data = vec![0; len as usize];
+        if !self.read_exact(&mut data)? {
+            return Ok(Frame::End(false));
+        }
+        self.hasher.update(&len.to_le_bytes());
+        self.hasher.update(&crc.to_le_bytes());
+        self.hasher.update(&data);
+        self.n += 1;
+
+        match crc32fast::hash(&data) == crc {
+            true => Ok(Frame::Record(data)),
+            false => Ok(Frame::Corrupt),
+        }
+    }
+
+    // read length and crc, or trailer marker and crc, None on end of file.
+    fn read_u32s(&mut self) -> Result<Option<(u32, u32)>> {
+        let mut buf = [0_u8; 8];
+        match self.read_exact(&mut buf)? {
+            true => {
+                let a = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                let b = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+                Ok(Some((a, b)))
+            }
+            false => Ok(None),
+        }
+    }
+
+    // return false on end of file.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => err_at!(IOError, msg: "{}", err),
+        }
+    }
+}
+
+// encode a record via the codec supplied to LruBuilder::snapshot_every.
+pub(crate) type Encoder<K, V> =
+    Arc<dyn Fn(&Record<K, V>) -> Result<Vec<u8>> + Send + Sync>;
+
+/// Periodic snapshots, built via [crate::LruBuilder::snapshot_every].
+pub struct Schedule<K, V> {
+    pub(crate) interval: Duration,
+    pub(crate) path: PathBuf,
+    pub(crate) encode: Encoder<K, V>,
+}
+
+impl<K, V> Schedule<K, V> {
+    /// Write a snapshot, of records supplied by `walk`, to a temporary file,
+    /// and rename it to `path` after rotating the older ones, so that `path`
+    /// is always a whole snapshot. Keep up to `keep` snapshots, including the
+    /// latest, as `path`, `path.1`, `path.2` and so on.
+    pub(crate) fn write<F>(&self, keep: usize, walk: F) -> Result<usize>
+    where
+        F: FnOnce(&mut dyn FnMut(&Record<K, V>) -> Result<()>) -> Result<usize>,
+    {
+        let tmp = to_path(&self.path, "tmp");
+        let file = err_at!(IOError, fs::File::create(&tmp))?;
+        let mut writer = Writer::new(io::BufWriter::new(file))?;
+        let n = walk(&mut |record| writer.append(&(self.encode)(record)?))?;
+        let file = err_at!(IOError, writer.finish()?.into_inner())?;
+        err_at!(IOError, file.sync_all())?;
+
+        for i in (1..keep.saturating_sub(1)).rev() {
+            let from = to_path(&self.path, &i.to_string());
+            if from.exists() {
+                let to = to_path(&self.path, &(i + 1).to_string());
+                err_at!(IOError, fs::rename(&from, &to))?;
+            }
+        }
+        if keep > 1 && self.path.exists() {
+            err_at!(IOError, fs::rename(&self.path, to_path(&self.path, "1")))?;
+        }
+        err_at!(IOError, fs::rename(&tmp, &self.path))?;
+
+        Ok(n)
+    }
+}
+
+// return `path` suffixed with `.ext`.
+fn to_path(path: &Path, ext: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
@@ -0,0 +1,23 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Fixed set of locks, a key is mapped to one of them by its hash.
+///
+/// Serializes read-modify-write operations, like `Lru::upsert`, with other
+/// writes on the same key across cache handles. Unrelated keys may share a
+/// lock.
+pub(crate) struct Stripes {
+    locks: Vec<Mutex<()>>,
+}
+
+impl Stripes {
+    pub fn new(n: usize) -> Stripes {
+        let locks = (0..std::cmp::max(n, 1)).map(|_| Mutex::new(())).collect();
+        Stripes { locks }
+    }
+
+    /// Lock the stripe for key's `hash`.
+    pub fn lock(&self, hash: u64) -> MutexGuard<'_, ()> {
+        let off = (hash % (self.locks.len() as u64)) as usize;
+        self.locks[off].lock().unwrap()
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Index of tag to entries carrying that tag, refer `Lru::set_tagged`.
+///
+/// Index holds the entry's version along with its key, an indexed entry is
+/// stale once its key is overwritten or evicted, and such entries are pruned
+/// as the index for a tag grows.
+pub(crate) struct TagIndex<K> {
+    tags: Mutex<HashMap<String, Tagged<K>>>,
+}
+
+struct Tagged<K> {
+    entries: Vec<(K, u64)>, // (key, version)
+    prune_at: usize,
+}
+
+impl<K> Default for TagIndex<K> {
+    fn default() -> TagIndex<K> {
+        TagIndex { tags: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<K> TagIndex<K> {
+    /// Index entry under `tag`, `is_live` is used to prune stale entries.
+    pub fn add<F>(&self, tag: &str, key: K, version: u64, is_live: F)
+    where
+        F: Fn(&K, u64) -> bool,
+    {
+        let mut tags = self.tags.lock().unwrap();
+        let tagged = tags
+            .entry(tag.to_string())
+            .or_insert_with(|| Tagged { entries: vec![], prune_at: 16 });
+
+        tagged.entries.push((key, version));
+        if tagged.entries.len() >= tagged.prune_at {
+            tagged.entries.retain(|(key, version)| is_live(key, *version));
+            tagged.prune_at = std::cmp::max(tagged.entries.len() * 2, 16);
+        }
+    }
+
+    /// Remove `tag` from index, return the entries that were carrying it.
+    pub fn take(&self, tag: &str) -> Vec<(K, u64)> {
+        let mut tags = self.tags.lock().unwrap();
+        tags.remove(tag).map(|tagged| tagged.entries).unwrap_or_default()
+    }
+}
@@ -0,0 +1,108 @@
+//! Module implement telemetry events raised by the cache and its evictor.
+//!
+//! Events are routed to the [TelemetrySink] configured via
+//! `LruBuilder::telemetry`. By default they are logged via the `log` crate,
+//! with the `log` feature, else dropped.
+
+#[cfg(feature = "log")]
+use log::{debug, error, warn};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Error, Stats};
+
+/// Lifecycle events raised by the cache, refer [TelemetrySink].
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// Evictor grew `max_entries`, refer `LruBuilder::adaptive_cap`.
+    Grow {
+        from: usize,
+        to: usize,
+        n_victims: usize,
+        n_hits: usize,
+    },
+    /// Evictor shrunk `max_entries`, refer `LruBuilder::adaptive_cap`.
+    Shrink {
+        from: usize,
+        to: usize,
+        n_victims: usize,
+        n_hits: usize,
+    },
+    /// Host is under memory pressure, evicting entries down to `floor`.
+    Pressure { floor: usize },
+    /// Number of access nodes diverge from the number of entries plus
+    /// tombstones, raised only with `leak-detect` feature.
+    Leak { n_nodes: usize, expected: usize },
+    /// Evictor failed and exited.
+    EvictorFail(&'a Error),
+    /// Cache is closed, with statistics at that point.
+    Closed(&'a Stats),
+    /// Access list is dropped, taking `elapsed` time.
+    ListDropped { elapsed: Duration },
+    /// Periodic snapshot of `n_entries` is written, taking `elapsed` time,
+    /// refer `LruBuilder::snapshot_every`.
+    Snapshot { n_entries: usize, elapsed: Duration },
+    /// Periodic snapshot failed, it is retried after the interval.
+    SnapshotFail(&'a Error),
+}
+
+/// Trait to receive telemetry events, implement this to route them into
+/// embedder's own telemetry instead of the global logger.
+///
+/// Events are raised from cache handles and the evictor thread, `event` shall
+/// return quickly and shall not call back into the cache.
+pub trait TelemetrySink: Send + Sync {
+    fn event(&self, event: Event<'_>);
+}
+
+/// Sink logging events via the `log` crate, enabled by the `log` feature.
+#[cfg(feature = "log")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+#[cfg(feature = "log")]
+impl TelemetrySink for LogSink {
+    fn event(&self, event: Event<'_>) {
+        match event {
+            Event::Grow { from, to, n_victims, n_hits } => debug!(
+                "grow max_entries {} -> {}, victims:{} hits:{}",
+                from, to, n_victims, n_hits
+            ),
+            Event::Shrink { from, to, n_victims, n_hits } => debug!(
+                "shrink max_entries {} -> {}, victims:{} hits:{}",
+                from, to, n_victims, n_hits
+            ),
+            Event::Pressure { floor } => {
+                debug!("memory pressure, evicting down to {}", floor)
+            }
+            Event::Leak { n_nodes, expected } => {
+                warn!("access nodes:{} entries+tombstones:{} diverge", n_nodes, expected)
+            }
+            Event::EvictorFail(err) => error!("evictor fail: {}", err),
+            Event::Closed(stats) => debug!("{:?}", stats),
+            Event::ListDropped { elapsed } => {
+                debug!("took {:?} to drop all the nodes", elapsed)
+            }
+            Event::Snapshot { n_entries, elapsed } => {
+                debug!("snapshot of {} entries took {:?}", n_entries, elapsed)
+            }
+            Event::SnapshotFail(err) => error!("snapshot fail: {}", err),
+        }
+    }
+}
+
+/// Sink dropping all events.
+impl TelemetrySink for () {
+    fn event(&self, _event: Event<'_>) {}
+}
+
+/// Return the default sink, [LogSink] with the `log` feature, else a sink
+/// that drops all events.
+pub fn default_sink() -> Arc<dyn TelemetrySink> {
+    #[cfg(feature = "log")]
+    let sink: Arc<dyn TelemetrySink> = Arc::new(LogSink);
+    #[cfg(not(feature = "log"))]
+    let sink: Arc<dyn TelemetrySink> = Arc::new(());
+    sink
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Rendezvous for `Lru::wait_for`, sets notify callers waiting for a key.
+///
+/// Waiters are not tracked per key, every set wakes all of them to check for
+/// their key, so sets pay for a notification only while there are waiters.
+#[derive(Default)]
+pub(crate) struct Waiters {
+    n_waiters: AtomicUsize,
+    // bumped on every notification.
+    generation: Mutex<u64>,
+    cond: Condvar,
+    #[cfg(feature = "async")]
+    notify: tokio::sync::Notify,
+}
+
+impl Waiters {
+    /// Wake all waiters, called after an entry is set.
+    pub fn notify(&self) {
+        if self.n_waiters.load(SeqCst) == 0 {
+            return;
+        }
+        *self.generation.lock().unwrap() += 1;
+        self.cond.notify_all();
+        #[cfg(feature = "async")]
+        self.notify.notify_waiters();
+    }
+
+    /// Invoke `check` until it returns Some, waiting for a notification in
+    /// between, or until `timeout` elapses.
+    pub fn wait<T, F>(&self, timeout: Duration, mut check: F) -> Option<T>
+    where
+        F: FnMut() -> Option<T>,
+    {
+        let _waiter = Waiter::new(&self.n_waiters);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let generation = *self.generation.lock().unwrap();
+            if let Some(res) = check() {
+                break Some(res);
+            }
+
+            let guard = self.generation.lock().unwrap();
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                break None;
+            }
+            let cond = |g: &mut u64| *g == generation;
+            let _ = self.cond.wait_timeout_while(guard, timeout, cond).unwrap();
+        }
+    }
+
+    /// Same as wait, but as a future.
+    #[cfg(feature = "async")]
+    pub async fn wait_async<T, F>(&self, timeout: Duration, mut check: F) -> Option<T>
+    where
+        F: FnMut() -> Option<T>,
+    {
+        let _waiter = Waiter::new(&self.n_waiters);
+        let deadline = Instant::now() + timeout;
+        loop {
+            // registered on creation, so a set after the check is not missed.
+            let notified = self.notify.notified();
+            if let Some(res) = check() {
+                break Some(res);
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                break None;
+            }
+            let _ = tokio::time::timeout(timeout, notified).await;
+        }
+    }
+}
+
+// counts a waiter for as long as it is alive, including when a wait_async
+// future is dropped.
+struct Waiter<'a> {
+    n_waiters: &'a AtomicUsize,
+}
+
+impl<'a> Waiter<'a> {
+    fn new(n_waiters: &'a AtomicUsize) -> Self {
+        n_waiters.fetch_add(1, SeqCst);
+        Waiter { n_waiters }
+    }
+}
+
+impl<'a> Drop for Waiter<'a> {
+    fn drop(&mut self) {
+        self.n_waiters.fetch_sub(1, SeqCst);
+    }
+}
@@ -0,0 +1,100 @@
+//! Property tests, random sequence of operations across cache handles are
+//! applied both on the cache and on a model, and the cache is checked
+//! against the model.
+
+use proptest::prelude::*;
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clru::{Lru, LruBuilder, SeededState};
+
+const N_HANDLES: usize = 4;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Set(usize, u16, u64),
+    Get(usize, u16),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (0..N_HANDLES, any::<u16>(), any::<u64>()).prop_map(|(h, k, v)| Op::Set(h, k, v)),
+        (0..N_HANDLES, any::<u16>()).prop_map(|(h, k)| Op::Get(h, k)),
+    ]
+}
+
+fn handles(max_entries: usize, seed: u64) -> Vec<Lru<u16, u64, SeededState>> {
+    let config = LruBuilder { max_entries, ..LruBuilder::default() };
+    let lru = config.deterministic(seed);
+    let mut handles: Vec<Lru<u16, u64, SeededState>> =
+        (1..N_HANDLES).map(|_| lru.clone()).collect();
+    handles.insert(0, lru);
+    handles
+}
+
+proptest! {
+    // with capacity to spare, nothing is evicted, every get shall return the
+    // value last set, via any handle.
+    #[test]
+    fn get_after_set(seed in any::<u64>(), ops in prop::collection::vec(op(), 1..2000)) {
+        let mut lrus = handles(1_000_000, seed);
+        let mut model: HashMap<u16, u64> = HashMap::new();
+
+        for op in ops.into_iter() {
+            match op {
+                Op::Set(h, key, value) => {
+                    let old = lrus[h].set(key, value).unwrap();
+                    prop_assert_eq!(old, model.insert(key, value));
+                }
+                Op::Get(h, key) => {
+                    let value = lrus[h].get(&key).unwrap();
+                    prop_assert_eq!(value, model.get(&key).copied());
+                }
+            }
+        }
+        prop_assert_eq!(lrus[0].len(), model.len());
+        prop_assert_eq!(lrus[0].stats().n_evicted, 0);
+    }
+
+    // under eviction, resident keys are a subset of inserted keys, their values
+    // are the last set, and once quiet the evictor brings the cache down to
+    // max_entries.
+    #[test]
+    fn evict_to_max_entries(
+        seed in any::<u64>(),
+        max_entries in 1_usize..256,
+        ops in prop::collection::vec(op(), 1..2000),
+    ) {
+        let mut lrus = handles(max_entries, seed);
+        let mut model: HashMap<u16, u64> = HashMap::new();
+
+        for op in ops.into_iter() {
+            match op {
+                Op::Set(h, key, value) => {
+                    lrus[h].set(key, value).unwrap();
+                    model.insert(key, value);
+                }
+                Op::Get(h, key) => {
+                    if let Some(value) = lrus[h].get(&key).unwrap() {
+                        prop_assert_eq!(Some(&value), model.get(&key))
+                    }
+                }
+            }
+        }
+
+        // evictor leaves the six most recent access nodes alone.
+        let limit = std::cmp::max(max_entries, 6);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while lrus[0].len() > limit && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        prop_assert!(lrus[0].len() <= limit, "{} {}", lrus[0].len(), max_entries);
+        prop_assert!(lrus[0].health().evictor_alive);
+
+        for (key, value, _) in lrus[0].iter_older_than(Duration::default()).unwrap() {
+            prop_assert_eq!(Some(&value), model.get(&key));
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Stress harness, run with `cargo test --features stress --test stress`.
+//!
+//! Each round runs a few threads, each thread picks operations and yields,
+//! or sleeps, at random points, driven by a seeded rng, so that every round
+//! explores a different interleaving of handles and the evictor. Set
+//! `STRESS_ROUNDS` to change the number of rounds, `STRESS_SEED` to replay a
+//! failed run.
+#![cfg(feature = "stress")]
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clru::{Lru, LruBuilder, SeededState};
+
+const N_THREADS: usize = 4;
+const N_OPS: usize = 500;
+const N_KEYS: u32 = 64;
+
+fn rounds() -> u64 {
+    match std::env::var("STRESS_ROUNDS") {
+        Ok(val) => val.parse().unwrap(),
+        Err(_) => 1000,
+    }
+}
+
+fn seed() -> u64 {
+    match std::env::var("STRESS_SEED") {
+        Ok(val) => val.parse().unwrap(),
+        Err(_) => rand::random(),
+    }
+}
+
+// yield, or sleep for a few micro-seconds, at random, to shake up the
+// interleaving of threads.
+fn schedule(rng: &mut StdRng) {
+    match rng.gen::<u8>() % 8 {
+        0 => thread::yield_now(),
+        1 => thread::sleep(Duration::from_micros(rng.gen::<u64>() % 50)),
+        _ => (),
+    }
+}
+
+fn new_lru(max_entries: usize, seed: u64) -> Lru<u32, i64, SeededState> {
+    let config = LruBuilder {
+        max_entries,
+        leak_threshold: 64,
+        ..LruBuilder::default()
+    };
+    config.deterministic(seed)
+}
+
+// run `callb` on `N_THREADS` handles, all released at once.
+fn spawn<F>(lru: &Lru<u32, i64, SeededState>, seed: u64, callb: F) -> Vec<Vec<i64>>
+where
+    F: 'static
+        + Send
+        + Sync
+        + Fn(usize, &mut Lru<u32, i64, SeededState>, &mut StdRng) -> Vec<i64>,
+{
+    let (barrier, callb) = (Arc::new(Barrier::new(N_THREADS)), Arc::new(callb));
+    let handles: Vec<thread::JoinHandle<Vec<i64>>> = (0..N_THREADS)
+        .map(|id| {
+            let (mut lru, barrier, callb) = (lru.clone(), barrier.clone(), callb.clone());
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(seed + (id as u64));
+                barrier.wait();
+                callb(id, &mut lru, &mut rng)
+            })
+        })
+        .collect();
+    handles.into_iter().map(|h| h.join().unwrap()).collect()
+}
+
+// evictor shall keep making passes, bring the cache down to max_entries
+// and free every node that it unlinks.
+fn check_evictor(lru: &Lru<u32, i64, SeededState>, seed: u64) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut health = lru.health();
+    while (lru.len() > lru.max_entries() || health.since_last_pass.is_none())
+        && Instant::now() < deadline
+    {
+        thread::sleep(Duration::from_millis(1));
+        health = lru.health();
+    }
+    assert!(health.evictor_alive, "seed:{} evictor exited", seed);
+    assert!(lru.len() <= lru.max_entries(), "seed:{} len:{}", seed, lru.len());
+
+    // evictor sleeps for no more than 10ms between passes.
+    let bound = Duration::from_secs(1);
+    match health.since_last_pass {
+        Some(since) => assert!(since < bound, "seed:{} evictor stuck {:?}", seed, since),
+        None => panic!("seed:{} evictor yet to complete a pass", seed),
+    }
+    assert_eq!(lru.stats().n_leak_alerts, 0, "seed:{} leaking nodes", seed);
+}
+
+// concurrent increments on a few keys, without eviction, shall add up.
+#[test]
+fn stress_lost_updates() {
+    let seed = seed();
+    println!("stress_lost_updates seed:{}", seed);
+
+    for round in 0..rounds() {
+        let seed = seed.wrapping_add(round);
+        let lru = new_lru(1_000_000, seed);
+
+        let tallies = spawn(&lru, seed, |_, lru, rng| {
+            let mut tally = vec![0; N_KEYS as usize];
+            for _ in 0..N_OPS {
+                let key = rng.gen::<u32>() % N_KEYS;
+                let delta = (rng.gen::<i64>() % 100) - 50;
+                lru.increment(key, delta).unwrap();
+                tally[key as usize] += delta;
+                schedule(rng);
+            }
+            tally
+        });
+
+        for key in 0..N_KEYS {
+            let total: i64 = tallies.iter().map(|tally| tally[key as usize]).sum();
+            match lru.get(&key).unwrap() {
+                Some(value) => assert_eq!(value, total, "seed:{} key:{}", seed, key),
+                None => assert_eq!(total, 0, "seed:{} key:{}", seed, key),
+            }
+        }
+        check_evictor(&lru, seed);
+        lru.close().unwrap();
+    }
+}
+
+// mix of operations with the cache well above its limit, so that the evictor
+// is unlinking nodes and removing entries under the threads. Values encode
+// their key, a value read back for another key points to a freed or reused
+// entry.
+#[test]
+fn stress_evict() {
+    let seed = seed();
+    println!("stress_evict seed:{}", seed);
+
+    for round in 0..rounds() {
+        let seed = seed.wrapping_add(round);
+        let lru = new_lru(16, seed);
+
+        spawn(&lru, seed, move |id, lru, rng| {
+            for _ in 0..N_OPS {
+                let key = rng.gen::<u32>() % N_KEYS;
+                let value = ((key as i64) << 32) | (id as i64);
+                match rng.gen::<u8>() % 4 {
+                    0 | 1 => {
+                        if let Some(value) = lru.get(&key).unwrap() {
+                            assert_eq!(value >> 32, key as i64, "seed:{}", seed)
+                        }
+                    }
+                    2 => {
+                        lru.set(key, value).unwrap();
+                    }
+                    _ => {
+                        lru.upsert(key, |old| match old {
+                            Some(old) => {
+                                assert_eq!(old >> 32, key as i64, "seed:{}", seed);
+                                *old
+                            }
+                            None => value,
+                        })
+                        .unwrap();
+                    }
+                }
+                schedule(rng);
+            }
+            vec![]
+        });
+
+        check_evictor(&lru, seed);
+        lru.close().unwrap();
+    }
+}